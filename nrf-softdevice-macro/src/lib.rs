@@ -10,6 +10,7 @@ use crate::ctxt::Ctxt;
 use crate::security_mode::SecurityMode;
 
 mod ctxt;
+mod schema;
 mod security_mode;
 mod uuid;
 
@@ -18,6 +19,8 @@ use crate::uuid::Uuid;
 #[derive(Debug, FromMeta)]
 struct ServiceArgs {
     uuid: Uuid,
+    #[darling(default)]
+    secondary: bool,
 }
 
 #[derive(Debug, FromMeta)]
@@ -59,6 +62,35 @@ struct Characteristic {
     vis: syn::Visibility,
 }
 
+/// Size of the value of a characteristic declaration, excluding the UUID: properties and value handle.
+const CHAR_DECL_VALUE_SIZE: usize = 3;
+/// Size of the value of an include declaration: start and end handles, and the 16-bit service UUID.
+const INCLUDE_VALUE_SIZE: usize = 6;
+const CCCD_VALUE_SIZE: usize = 2;
+
+/// Length of a descriptor value, if it can be determined from its syntax alone.
+fn const_len(expr: &syn::Expr) -> Option<usize> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(s), ..
+        }) => Some(s.value().len()),
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::ByteStr(s),
+            ..
+        }) => Some(s.value().len()),
+        syn::Expr::Array(a) => Some(a.elems.len()),
+        syn::Expr::Repeat(r) => match &*r.len {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(i), ..
+            }) => i.base10_parse().ok(),
+            _ => None,
+        },
+        syn::Expr::Reference(r) => const_len(&r.expr),
+        syn::Expr::Paren(p) => const_len(&p.expr),
+        _ => None,
+    }
+}
+
 #[proc_macro_attribute]
 pub fn gatt_server(_args: TokenStream, item: TokenStream) -> TokenStream {
     // Context for error reporting
@@ -77,24 +109,89 @@ pub fn gatt_server(_args: TokenStream, item: TokenStream) -> TokenStream {
             return TokenStream::new();
         }
     };
-    let fields = struct_fields.named.iter().cloned().collect::<Vec<syn::Field>>();
+    let mut fields = struct_fields.named.iter().cloned().collect::<Vec<syn::Field>>();
 
     let struct_name = struc.ident.clone();
     let event_enum_name = format_ident!("{}Event", struct_name);
 
+    let mut code_register = TokenStream2::new();
     let mut code_register_init = TokenStream2::new();
     let mut code_on_write = TokenStream2::new();
     let mut code_event_enum = TokenStream2::new();
 
     let ble = quote!(::nrf_softdevice::ble);
 
-    for field in fields.iter() {
+    let mut registered = Vec::new();
+    // JSON fragments of the server schema, alternating with the services' `GATT_SCHEMA` consts.
+    let mut schema_parts = Vec::new();
+    let mut schema_buf = schema::Object::new()
+        .str("name", &struct_name.to_string())
+        .open("services");
+    schema_buf.push('[');
+    let mut services = Vec::new();
+    let mut include_count = 0usize;
+    for field in fields.iter_mut() {
         let name = field.ident.as_ref().unwrap();
         let ty = &field.ty;
         let span = field.ty.span();
+
+        let mut includes = Vec::new();
+        field.attrs.retain(|attr| {
+            if !attr.path.is_ident("include") {
+                return true;
+            }
+            match attr.parse_meta() {
+                Ok(syn::Meta::List(list)) => {
+                    for nested in list.nested {
+                        match nested {
+                            syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.get_ident().is_some() => {
+                                let include = p.get_ident().unwrap().clone();
+                                if !registered.contains(&include) {
+                                    ctxt.error_spanned_by(
+                                        &include,
+                                        "included services must be declared before the services that include them",
+                                    );
+                                }
+                                includes.push(include);
+                            }
+                            other => ctxt.error_spanned_by(other, "expected the name of a service field"),
+                        }
+                    }
+                }
+                _ => ctxt.error_spanned_by(attr, "expected #[include(service, ...)]"),
+            }
+            false
+        });
+
+        if includes.is_empty() {
+            code_register.extend(quote_spanned!(span=>
+                let #name = #ty::new(sd)?;
+            ));
+        } else {
+            code_register.extend(quote_spanned!(span=>
+                let #name = #ty::new_with_includes(sd, &[#(#includes.__service_handle()),*])?;
+            ));
+        }
         code_register_init.extend(quote_spanned!(span=>
-            #name: #ty::new(sd)?,
+            #name,
         ));
+        registered.push(name.clone());
+        services.push(ty.clone());
+        include_count += includes.len();
+
+        if services.len() > 1 {
+            schema_buf.push(',');
+        }
+        schema_buf.push_str(
+            &schema::Object::new()
+                .str("name", &name.to_string())
+                .str("type", &ty.to_token_stream().to_string())
+                .array("includes", includes.iter().map(|i| schema::string(&i.to_string())))
+                .open("service"),
+        );
+        schema_parts.push(quote!(#schema_buf));
+        schema_parts.push(quote!(<#ty as #ble::gatt_server::Service>::GATT_SCHEMA));
+        schema_buf = String::from("}");
 
         if let syn::Type::Path(p) = &field.ty {
             let name_pascal = format_ident!("{}", inflector::cases::pascalcase::to_pascal_case(&name.to_string()));
@@ -115,12 +212,36 @@ pub fn gatt_server(_args: TokenStream, item: TokenStream) -> TokenStream {
     struct_fields.named = syn::punctuated::Punctuated::from_iter(fields);
     let struc_vis = struc.vis.clone();
 
+    schema_buf.push_str("]}");
+    schema_parts.push(quote!(#schema_buf));
+
     let result = quote! {
         #struc
 
         impl #struct_name {
+            /// Estimated attribute table size needed by this server, for `Config::gatts_attr_tab_size`.
+            #struct_vis const ATTR_TAB_SIZE: u32 = #ble::gatt_server::attr_tab_size(
+                #include_count #(+ <#services as #ble::gatt_server::Service>::ATTR_COUNT)*,
+                #include_count * #INCLUDE_VALUE_SIZE #(+ <#services as #ble::gatt_server::Service>::ATTR_VALUE_SIZE)*,
+            );
+
+            /// Number of vendor-specific UUID bases used by this server, for `Config::common_vs_uuid`.
+            #struct_vis const VS_UUID_COUNT: u8 = #ble::gatt_server::vs_uuid_count(&[#(<#services as #ble::gatt_server::Service>::VS_UUID_BASES),*]);
+
+            /// Layout of this server as JSON, including the layout of each service.
+            #struct_vis const GATT_SCHEMA: &'static str = {
+                const PARTS: &[&str] = &[#(#schema_parts),*];
+                const BYTES: [u8; #ble::gatt_server::schema_len(PARTS)] = #ble::gatt_server::schema_concat(PARTS);
+                match ::core::str::from_utf8(&BYTES) {
+                    Ok(schema) => schema,
+                    Err(_) => panic!("invalid GATT schema"),
+                }
+            };
+
             #struct_vis fn new(sd: &mut ::nrf_softdevice::Softdevice) -> Result<Self, #ble::gatt_server::RegisterError>
             {
+                #code_register
+
                 Ok(Self {
                     #code_register_init
                 })
@@ -230,6 +351,16 @@ pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
 
     let ble = quote!(::nrf_softdevice::ble);
 
+    let uuid = args.uuid;
+    let mut attr_count = 1;
+    let mut attr_value_size = uuid.size();
+    let mut code_attr_value_size = TokenStream2::new();
+    let mut vs_uuid_bases = Vec::new();
+    if let Some(base) = uuid.vs_base() {
+        vs_uuid_bases.push(base);
+    }
+
+    let mut schema_chars = Vec::new();
     for ch in &chars {
         let name_pascal = inflector::cases::pascalcase::to_pascal_case(&ch.name);
         let char_name = format_ident!("{}", ch.name);
@@ -275,6 +406,50 @@ pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
             })
             .collect();
 
+        // Declaration and value, plus CCCD and descriptors.
+        attr_count += 2 + ch.args.descriptor.len();
+        attr_value_size += CHAR_DECL_VALUE_SIZE + uuid.size();
+        code_attr_value_size.extend(quote!(+ #ty_as_val::MAX_SIZE));
+        if notify || indicate {
+            attr_count += 1;
+            attr_value_size += CCCD_VALUE_SIZE;
+        }
+        for descriptor in &ch.args.descriptor {
+            attr_value_size += descriptor.value.as_ref().and_then(const_len).unwrap_or(0);
+        }
+        for base in [uuid.vs_base()]
+            .into_iter()
+            .chain(ch.args.descriptor.iter().map(|d| d.uuid.vs_base()))
+            .flatten()
+        {
+            if !vs_uuid_bases.contains(&base) {
+                vs_uuid_bases.push(base);
+            }
+        }
+
+        schema_chars.push(
+            schema::Object::new()
+                .str("name", &ch.name)
+                .str("uuid", &uuid.to_string())
+                .str("type", &ty.to_token_stream().to_string())
+                .bool("read", read)
+                .bool("write", write)
+                .bool("write_without_response", write_without_response)
+                .bool("notify", notify)
+                .bool("indicate", indicate)
+                .opt_str("security", ch.args.security.map(|s| format!("{:?}", s)).as_deref())
+                .array(
+                    "descriptors",
+                    ch.args.descriptor.iter().map(|d| {
+                        schema::Object::new()
+                            .str("uuid", &d.uuid.to_string())
+                            .opt_str("security", d.security.map(|s| format!("{:?}", s)).as_deref())
+                            .finish()
+                    }),
+                )
+                .finish(),
+        );
+
         let security = if let Some(security) = ch.args.security {
             let security_inner = security.to_token_stream();
             quote!(attr = attr.read_security(#security_inner).write_security(#security_inner))
@@ -443,7 +618,26 @@ pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
         }
     }
 
-    let uuid = args.uuid;
+    fields.push(syn::Field {
+        ident: Some(format_ident!("__service_handle")),
+        ty: syn::Type::Verbatim(quote!(#ble::gatt_server::ServiceHandle)),
+        attrs: Vec::new(),
+        colon_token: Default::default(),
+        vis: syn::Visibility::Inherited,
+    });
+
+    let schema = schema::Object::new()
+        .str("name", &struct_name.to_string())
+        .str("uuid", &uuid.to_string())
+        .bool("secondary", args.secondary)
+        .array("characteristics", schema_chars)
+        .finish();
+
+    let service_builder_new = if args.secondary {
+        quote!(#ble::gatt_server::builder::ServiceBuilder::new_secondary)
+    } else {
+        quote!(#ble::gatt_server::builder::ServiceBuilder::new)
+    };
     struct_fields.named = syn::punctuated::Punctuated::from_iter(fields);
     let struc_vis = struc.vis.clone();
 
@@ -454,23 +648,45 @@ pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
         impl #struct_name {
             #struct_vis fn new(sd: &mut ::nrf_softdevice::Softdevice) -> Result<Self, #ble::gatt_server::RegisterError>
             {
-                let mut service_builder = #ble::gatt_server::builder::ServiceBuilder::new(sd, #uuid)?;
+                Self::new_with_includes(sd, &[])
+            }
 
-                #code_build_chars
+            #struct_vis fn new_with_includes(
+                sd: &mut ::nrf_softdevice::Softdevice,
+                includes: &[#ble::gatt_server::ServiceHandle],
+            ) -> Result<Self, #ble::gatt_server::RegisterError>
+            {
+                let mut service_builder = #service_builder_new(sd, #uuid)?;
 
-                let _ = service_builder.build();
+                for service in includes {
+                    service_builder.include_service(service)?;
+                }
+
+                #code_build_chars
 
                 Ok(Self {
+                    __service_handle: service_builder.build(),
                     #code_struct_init
                 })
             }
 
+            // Reserved name, so it can't clash with the user's own items.
+            #[doc(hidden)]
+            #struct_vis fn __service_handle(&self) -> #ble::gatt_server::ServiceHandle {
+                self.__service_handle
+            }
+
             #code_impl
         }
 
         impl #ble::gatt_server::Service for #struct_name {
             type Event = #event_enum_name;
 
+            const ATTR_COUNT: usize = #attr_count;
+            const ATTR_VALUE_SIZE: usize = #attr_value_size #code_attr_value_size;
+            const VS_UUID_BASES: &'static [[u8; 16]] = &[#([#(#vs_uuid_bases),*]),*];
+            const GATT_SCHEMA: &'static str = #schema;
+
             fn on_write(&self, handle: u16, data: &[u8]) -> Option<Self::Event> {
                 #code_on_write
                 None
@@ -502,6 +718,12 @@ pub fn gatt_client(args: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    if args.secondary {
+        return Error::custom("gatt_client does not support secondary services")
+            .write_errors()
+            .into();
+    }
+
     let mut chars = Vec::new();
 
     let struct_fields = match &mut struc.fields {
@@ -799,3 +1021,126 @@ pub fn gatt_client(args: TokenStream, item: TokenStream) -> TokenStream {
         Err(e) => e.into(),
     }
 }
+
+#[proc_macro_attribute]
+pub fn gatt_client_set(_args: TokenStream, item: TokenStream) -> TokenStream {
+    // Context for error reporting
+    let ctxt = Ctxt::new();
+
+    let struc = syn::parse_macro_input!(item as syn::ItemStruct);
+
+    let struct_fields = match &struc.fields {
+        syn::Fields::Named(n) => n,
+        _ => {
+            let s = struc.ident;
+
+            ctxt.error_spanned_by(s, "gatt_client_set structs must have named fields, not tuples.");
+
+            return TokenStream::new();
+        }
+    };
+    let fields = struct_fields.named.iter().cloned().collect::<Vec<syn::Field>>();
+
+    let struct_name = struc.ident.clone();
+    let event_enum_name = format_ident!("{}Event", struct_name);
+
+    let mut code_on_hvx = TokenStream2::new();
+    let mut code_uuid = TokenStream2::new();
+    let mut code_disc_new = TokenStream2::new();
+    let mut code_disc_char = TokenStream2::new();
+    let mut code_disc_done = TokenStream2::new();
+    let mut code_event_enum = TokenStream2::new();
+
+    let ble = quote!(::nrf_softdevice::ble);
+
+    for (i, field) in fields.iter().enumerate() {
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let span = field.ty.span();
+        let name_pascal = format_ident!("{}", inflector::cases::pascalcase::to_pascal_case(&name.to_string()));
+        let client = quote_spanned!(span=> <#ty as #ble::gatt_client::Client>);
+
+        code_event_enum.extend(quote_spanned!(span=>
+            #name_pascal(#client::Event),
+        ));
+        code_on_hvx.extend(quote_spanned!(span=>
+            if let Some(e) = #client::on_hvx(&self.#name, conn, type_, handle, data) {
+                return Some(#event_enum_name::#name_pascal(e));
+            }
+        ));
+        code_uuid.extend(quote_spanned!(span=>
+            #i => #client::uuid(),
+        ));
+        code_disc_new.extend(quote_spanned!(span=>
+            #name: #client::new_undiscovered(conn.clone()),
+        ));
+        code_disc_char.extend(quote_spanned!(span=>
+            #i => #client::discovered_characteristic(&mut self.#name, characteristic, descriptors),
+        ));
+        code_disc_done.extend(quote_spanned!(span=>
+            #i => #client::discovery_complete(&mut self.#name),
+        ));
+    }
+
+    let service_count = fields.len();
+    let vis = struc.vis.clone();
+    let result = quote! {
+        #struc
+
+        const _: () = assert!(
+            #service_count <= #ble::gatt_client::CLIENT_SET_MAX,
+            "too many services in gatt_client_set, the maximum is CLIENT_SET_MAX"
+        );
+
+        impl #ble::gatt_client::ClientSet for #struct_name {
+            type Event = #event_enum_name;
+
+            const SERVICE_COUNT: usize = #service_count;
+
+            fn on_hvx(&self, conn: &#ble::Connection, type_: #ble::gatt_client::HvxType, handle: u16, data: &[u8]) -> Option<Self::Event> {
+                #code_on_hvx
+                None
+            }
+
+            fn service_uuid(index: usize) -> #ble::Uuid {
+                match index {
+                    #code_uuid
+                    _ => panic!("invalid service index"),
+                }
+            }
+
+            fn new_undiscovered(conn: #ble::Connection) -> Self {
+                Self {
+                    #code_disc_new
+                }
+            }
+
+            fn discovered_characteristic(
+                &mut self,
+                index: usize,
+                characteristic: &#ble::gatt_client::Characteristic,
+                descriptors: &[#ble::gatt_client::Descriptor],
+            ) {
+                match index {
+                    #code_disc_char
+                    _ => {}
+                }
+            }
+
+            fn discovery_complete(&mut self, index: usize) -> Result<(), #ble::gatt_client::DiscoverError> {
+                match index {
+                    #code_disc_done
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        #vis enum #event_enum_name {
+            #code_event_enum
+        }
+    };
+    match ctxt.check() {
+        Ok(()) => result.into(),
+        Err(e) => e.into(),
+    }
+}
@@ -0,0 +1,89 @@
+//! GATT layout described by the macros, serialized as JSON for use by host tooling.
+//!
+//! Each `#[gatt_service]` and `#[gatt_server]` struct gets a `GATT_SCHEMA` associated const holding
+//! its layout. The server's schema embeds those of its services, which it only knows by type, so it
+//! is assembled from the services' consts at compile time. The application can write it out from a test or a host-side binary, so the export is
+//! tracked by cargo like any other build output.
+
+use std::fmt::Write;
+
+/// Minimal JSON object builder. Values are written in insertion order.
+pub struct Object {
+    buf: String,
+}
+
+impl Object {
+    pub fn new() -> Self {
+        Self { buf: String::from("{") }
+    }
+
+    fn key(&mut self, key: &str) {
+        if self.buf.len() > 1 {
+            self.buf.push(',');
+        }
+        self.buf.push_str(&string(key));
+        self.buf.push(':');
+    }
+
+    pub fn str(mut self, key: &str, value: &str) -> Self {
+        self.key(key);
+        self.buf.push_str(&string(value));
+        self
+    }
+
+    pub fn opt_str(mut self, key: &str, value: Option<&str>) -> Self {
+        self.key(key);
+        match value {
+            Some(v) => self.buf.push_str(&string(v)),
+            None => self.buf.push_str("null"),
+        }
+        self
+    }
+
+    pub fn bool(mut self, key: &str, value: bool) -> Self {
+        self.key(key);
+        self.buf.push_str(if value { "true" } else { "false" });
+        self
+    }
+
+    /// Add an array of already serialized JSON values.
+    pub fn array(mut self, key: &str, values: impl IntoIterator<Item = String>) -> Self {
+        self.key(key);
+        self.buf.push('[');
+        for (i, v) in values.into_iter().enumerate() {
+            if i != 0 {
+                self.buf.push(',');
+            }
+            self.buf.push_str(&v);
+        }
+        self.buf.push(']');
+        self
+    }
+
+    /// Finish the object up to the value of `key`. The caller appends the value, then the closing `}`.
+    pub fn open(mut self, key: &str) -> String {
+        self.key(key);
+        self.buf
+    }
+
+    pub fn finish(mut self) -> String {
+        self.buf.push('}');
+        self.buf
+    }
+}
+
+/// Serialize `s` as a JSON string.
+pub fn string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(res, "\\u{:04x}", c as u32).unwrap(),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
@@ -44,3 +44,39 @@ impl quote::ToTokens for Uuid {
         }
     }
 }
+
+impl std::fmt::Display for Uuid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Uuid::Uuid16(u) => write!(f, "{:04x}", u),
+            Uuid::Uuid128(u) => {
+                let mut bytes = *u;
+                bytes.reverse();
+                write!(f, "{}", uuid::Uuid::from_bytes(bytes).hyphenated())
+            }
+        }
+    }
+}
+
+impl Uuid {
+    /// Size of the UUID in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            Uuid::Uuid16(_) => 2,
+            Uuid::Uuid128(_) => 16,
+        }
+    }
+
+    /// Vendor-specific base of a 128-bit UUID, with the 16-bit part zeroed.
+    pub fn vs_base(&self) -> Option<[u8; 16]> {
+        match self {
+            Uuid::Uuid16(_) => None,
+            Uuid::Uuid128(u) => {
+                let mut base = *u;
+                base[12] = 0;
+                base[13] = 0;
+                Some(base)
+            }
+        }
+    }
+}
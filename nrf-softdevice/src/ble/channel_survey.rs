@@ -0,0 +1,129 @@
+//! QoS channel survey, measuring the energy on each BLE channel.
+//!
+//! The survey role must be enabled in the softdevice configuration with
+//! [`ConfigBuilder::qos_channel_survey`](crate::ConfigBuilder::qos_channel_survey) (or by setting
+//! `qos_channel_survey_role_available` in [`Config::gap_role_count`](crate::Config::gap_role_count)).
+//!
+//! The measurements can be turned into a channel map avoiding busy channels with
+//! [`ChannelEnergy::channel_map`], and applied with [`options::set_channel_map`](super::options::set_channel_map).
+
+use crate::util::{get_union_field, OnDrop, Portal};
+use crate::{raw, RawError, Softdevice};
+
+const CHANNEL_COUNT: usize = raw::BLE_GAP_CHANNEL_COUNT as usize;
+const DATA_CHANNEL_COUNT: usize = 37;
+const POWER_LEVEL_INVALID: i8 = raw::BLE_GAP_POWER_LEVEL_INVALID as i8;
+
+/// Energy measured on each channel, in dBm, indexed by channel index (0 to 36 for data channels,
+/// 37 to 39 for advertising channels).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelEnergy([i8; CHANNEL_COUNT]);
+
+impl Default for ChannelEnergy {
+    fn default() -> Self {
+        Self([POWER_LEVEL_INVALID; CHANNEL_COUNT])
+    }
+}
+
+impl ChannelEnergy {
+    /// Energy measured on the channel, or `None` if it wasn't measured.
+    pub fn get(&self, channel: u8) -> Option<i8> {
+        match self.0.get(usize::from(channel)) {
+            None | Some(&POWER_LEVEL_INVALID) => None,
+            Some(&energy) => Some(energy),
+        }
+    }
+
+    /// Merge another report into this one, keeping the highest energy measured on each channel.
+    pub fn merge_max(&mut self, other: &ChannelEnergy) {
+        for (a, &b) in self.0.iter_mut().zip(other.0.iter()) {
+            if b != POWER_LEVEL_INVALID && (*a == POWER_LEVEL_INVALID || b > *a) {
+                *a = b;
+            }
+        }
+    }
+
+    /// Build a data channel map enabling the channels whose energy is at most `max_energy_dbm`.
+    ///
+    /// Channels that weren't measured are enabled. If less than two channels qualify, the quietest
+    /// ones are added, as the Bluetooth specification requires at least two channels.
+    pub fn channel_map(&self, max_energy_dbm: i8) -> [u8; 5] {
+        let mut ch_map = [0u8; 5];
+        let mut count = 0;
+        for channel in 0..DATA_CHANNEL_COUNT {
+            if self.get(channel as u8).map_or(true, |energy| energy <= max_energy_dbm) {
+                ch_map[channel / 8] |= 1 << (channel % 8);
+                count += 1;
+            }
+        }
+
+        while count < 2 {
+            let quietest = (0..DATA_CHANNEL_COUNT)
+                .filter(|&channel| ch_map[channel / 8] & (1 << (channel % 8)) == 0)
+                .min_by_key(|&channel| self.0[channel]);
+            let Some(channel) = quietest else { break };
+            ch_map[channel / 8] |= 1 << (channel % 8);
+            count += 1;
+        }
+
+        ch_map
+    }
+}
+
+pub(crate) static PORTAL: Portal<*const raw::ble_evt_t> = Portal::new();
+
+/// Run a channel survey, calling `f` with each report until it returns `Some`.
+///
+/// `interval_us` is the requested average interval between reports, in microseconds, or
+/// [`raw::BLE_GAP_QOS_CHANNEL_SURVEY_INTERVAL_CONTINUOUS`] to measure at every opportunity.
+/// The survey has the lowest priority of all radio activities, so reports can be less frequent.
+///
+/// Returns [`RawError::Resources`] if the survey role wasn't enabled in the configuration.
+pub async fn survey<F, R>(_sd: &Softdevice, interval_us: u32, mut f: F) -> Result<R, RawError>
+where
+    F: FnMut(&ChannelEnergy) -> Option<R>,
+{
+    let ret = unsafe { raw::sd_ble_gap_qos_channel_survey_start(interval_us) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_ble_gap_qos_channel_survey_start err {:?}", err);
+        return Err(err);
+    }
+
+    let _d = OnDrop::new(|| {
+        let ret = unsafe { raw::sd_ble_gap_qos_channel_survey_stop() };
+        if let Err(_err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_qos_channel_survey_stop err {:?}", _err);
+        }
+    });
+
+    debug!("Channel survey started");
+    let res = PORTAL
+        .wait_many(|ble_evt| unsafe {
+            match (*ble_evt).header.evt_id as u32 {
+                raw::BLE_GAP_EVTS_BLE_GAP_EVT_QOS_CHANNEL_SURVEY_REPORT => {
+                    let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+                    let energy = ChannelEnergy(gap_evt.params.qos_channel_survey_report.channel_energy);
+                    f(&energy)
+                }
+                _ => None,
+            }
+        })
+        .await;
+
+    Ok(res)
+}
+
+/// Run a channel survey until `reports` reports were received, returning the highest energy
+/// measured on each channel.
+pub async fn survey_max(sd: &Softdevice, interval_us: u32, reports: usize) -> Result<ChannelEnergy, RawError> {
+    let mut energy = ChannelEnergy::default();
+    let mut received = 0;
+    survey(sd, interval_us, |report| {
+        energy.merge_max(report);
+        received += 1;
+        (received >= reports).then_some(())
+    })
+    .await?;
+    Ok(energy)
+}
@@ -0,0 +1,210 @@
+//! Connection parameter negotiation.
+//!
+//! A [`ConnParamsPolicy`] can be attached to a connection with [`Connection::set_conn_params_policy`].
+//!
+//! - On central connections, it decides how to answer the connection parameter update requests sent by the peripheral.
+//!   Without a policy, all requests are accepted.
+//! - On peripheral connections, it describes the parameters the peripheral wants, which [`negotiate`] then requests
+//!   from the central, similar to the nRF5 SDK `ble_conn_params` module.
+
+#[cfg(feature = "ble-peripheral")]
+use core::future::Future;
+#[cfg(feature = "ble-peripheral")]
+use core::pin::pin;
+
+#[cfg(feature = "ble-peripheral")]
+use futures::future::{select, Either};
+
+use raw::ble_gap_conn_params_t;
+
+use crate::ble::Connection;
+#[cfg(feature = "ble-peripheral")]
+use crate::ble::{HciStatus, SetConnParamsError};
+use crate::raw;
+
+/// Answer to a connection parameter update request from the peripheral.
+#[derive(Debug, Clone, Copy)]
+pub enum ConnParamsResponse {
+    /// Use the parameters requested by the peripheral.
+    Accept,
+    /// Reject the request, keeping the current parameters.
+    Reject,
+    /// Use these parameters instead.
+    Counter(ble_gap_conn_params_t),
+}
+
+// `ble_gap_conn_params_t` doesn't implement `defmt::Format`, so it can't be derived.
+#[cfg(feature = "defmt")]
+impl defmt::Format for ConnParamsResponse {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            Self::Accept => defmt::write!(fmt, "Accept"),
+            Self::Reject => defmt::write!(fmt, "Reject"),
+            Self::Counter(params) => defmt::write!(
+                fmt,
+                "Counter {{ min_conn_interval: {}, max_conn_interval: {}, slave_latency: {}, conn_sup_timeout: {} }}",
+                params.min_conn_interval,
+                params.max_conn_interval,
+                params.slave_latency,
+                params.conn_sup_timeout
+            ),
+        }
+    }
+}
+
+pub trait ConnParamsPolicy {
+    /// Central: handle a connection parameter update request from the peripheral.
+    fn on_update_request(&self, conn: &Connection, requested: &ble_gap_conn_params_t) -> ConnParamsResponse {
+        let _ = (conn, requested);
+        ConnParamsResponse::Accept
+    }
+
+    /// Peripheral: the parameters to request from the central, or `None` to keep whatever the central chose.
+    fn desired_conn_params(&self, conn: &Connection) -> Option<ble_gap_conn_params_t> {
+        let _ = conn;
+        None
+    }
+
+    /// Peripheral: whether `params` are good enough to stop negotiating.
+    ///
+    /// By default, the connection interval must be in the desired range, and the slave latency
+    /// and supervision timeout must match the desired ones.
+    fn is_acceptable(&self, conn: &Connection, params: &ble_gap_conn_params_t) -> bool {
+        match self.desired_conn_params(conn) {
+            None => true,
+            Some(desired) => {
+                (desired.min_conn_interval..=desired.max_conn_interval).contains(&params.max_conn_interval)
+                    && desired.slave_latency == params.slave_latency
+                    && desired.conn_sup_timeout == params.conn_sup_timeout
+            }
+        }
+    }
+
+    /// Peripheral: time to wait after connecting before the first request, in milliseconds.
+    fn first_update_delay_ms(&self) -> u32 {
+        5_000
+    }
+
+    /// Peripheral: time to wait between requests, in milliseconds.
+    fn next_update_delay_ms(&self) -> u32 {
+        30_000
+    }
+
+    /// Peripheral: time to wait for the central to answer a request, in milliseconds.
+    ///
+    /// The default is the 30 s L2CAP signaling timeout.
+    fn update_timeout_ms(&self) -> u32 {
+        30_000
+    }
+
+    /// Peripheral: number of requests to send before giving up.
+    fn max_update_count(&self) -> u8 {
+        3
+    }
+
+    /// Peripheral: whether to disconnect when negotiation fails.
+    fn disconnect_on_fail(&self) -> bool {
+        false
+    }
+}
+
+/// Central: answer a connection parameter update request according to the connection's policy.
+#[cfg(feature = "ble-central")]
+pub(crate) fn on_update_request(conn_handle: u16, requested: &ble_gap_conn_params_t) {
+    let response = Connection::from_handle(conn_handle)
+        .and_then(|conn| {
+            conn.conn_params_policy()
+                .map(|policy| policy.on_update_request(&conn, requested))
+        })
+        .unwrap_or(ConnParamsResponse::Accept);
+
+    let params: *const ble_gap_conn_params_t = match &response {
+        ConnParamsResponse::Accept => requested,
+        // A null pointer rejects the request.
+        ConnParamsResponse::Reject => core::ptr::null(),
+        ConnParamsResponse::Counter(params) => params,
+    };
+
+    let ret = unsafe { raw::sd_ble_gap_conn_param_update(conn_handle, params) };
+    if let Err(_err) = crate::RawError::convert(ret) {
+        warn!("sd_ble_gap_conn_param_update err {:?}", _err);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg(feature = "ble-peripheral")]
+pub enum NegotiateError {
+    Disconnected,
+    /// The central didn't agree to acceptable parameters after [`ConnParamsPolicy::max_update_count`] requests.
+    Failed,
+    Raw(crate::RawError),
+}
+
+#[cfg(feature = "ble-peripheral")]
+impl From<SetConnParamsError> for NegotiateError {
+    fn from(err: SetConnParamsError) -> Self {
+        match err {
+            SetConnParamsError::Disconnected => Self::Disconnected,
+            SetConnParamsError::Raw(err) => Self::Raw(err),
+        }
+    }
+}
+
+/// Peripheral: negotiate connection parameters with the central according to the connection's policy.
+///
+/// `delay_ms` must return a future that completes after the given number of milliseconds, for
+/// example `|ms| embassy_time::Timer::after_millis(ms.into())`. It is also used to stop waiting for
+/// an answer after [`ConnParamsPolicy::update_timeout_ms`], so this always completes in bounded time.
+///
+/// Returns once acceptable parameters are in use, immediately if the connection has no policy or
+/// the policy has no desired parameters.
+///
+#[cfg(feature = "ble-peripheral")]
+pub async fn negotiate<F, Fut>(conn: &Connection, mut delay_ms: F) -> Result<(), NegotiateError>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let Some(policy) = conn.conn_params_policy() else {
+        return Ok(());
+    };
+    let Some(desired) = policy.desired_conn_params(conn) else {
+        return Ok(());
+    };
+
+    delay_ms(policy.first_update_delay_ms()).await;
+
+    for i in 0..policy.max_update_count() {
+        if i != 0 {
+            delay_ms(policy.next_update_delay_ms()).await;
+        }
+
+        if policy.is_acceptable(conn, &conn.conn_params()) {
+            return Ok(());
+        }
+
+        debug!("conn params negotiation: request {:?}", i);
+        let update = pin!(conn.set_conn_params_and_wait(desired));
+        let timeout = pin!(delay_ms(policy.update_timeout_ms()));
+        match select(update, timeout).await {
+            Either::Left((params, _)) => {
+                if policy.is_acceptable(conn, &params?) {
+                    return Ok(());
+                }
+            }
+            Either::Right(_) => debug!("conn params negotiation: no answer"),
+        }
+    }
+
+    if policy.is_acceptable(conn, &conn.conn_params()) {
+        return Ok(());
+    }
+
+    warn!("conn params negotiation failed");
+    if policy.disconnect_on_fail() {
+        conn.disconnect_with_reason(HciStatus::CONN_INTERVAL_UNACCEPTABLE)
+            .map_err(|_| NegotiateError::Disconnected)?;
+    }
+    Err(NegotiateError::Failed)
+}
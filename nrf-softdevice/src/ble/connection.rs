@@ -1,19 +1,32 @@
-use core::cell::{Cell, UnsafeCell};
+use core::cell::{Cell, RefCell, UnsafeCell};
 use core::iter::FusedIterator;
+use core::task::{Context, Poll};
 
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use futures::future::poll_fn;
 use raw::ble_gap_conn_params_t;
 
-use super::{HciStatus, PhySet};
+use super::conn_params::ConnParamsPolicy;
+#[cfg(feature = "ble-peripheral")]
+use super::options::{self, OptionError};
+use super::phy::PhyPolicy;
+use super::{HciStatus, Phy, PhySet, TxPower};
 #[cfg(feature = "ble-central")]
 use crate::ble::gap::default_security_params;
 #[cfg(feature = "ble-sec")]
 use crate::ble::security::SecurityHandler;
 use crate::ble::types::{Address, AddressType, Role, SecurityMode};
-use crate::util::get_union_field;
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+use crate::ppi;
+use crate::util::{get_union_field, Portal};
 use crate::{raw, RawError};
 
 #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
 const BLE_GAP_DATA_LENGTH_DEFAULT: u8 = 27; //  The stack's default data length. <27-251>
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+const BLE_GAP_DATA_TIME_DEFAULT: u16 = 328; //  Time to transmit the default data length on the 1M PHY, in us.
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -76,6 +89,66 @@ impl From<RawError> for SetConnParamsError {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SetTxPowerError {
+    Disconnected,
+    Raw(RawError),
+}
+
+impl From<DisconnectedError> for SetTxPowerError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<RawError> for SetTxPowerError {
+    fn from(err: RawError) -> Self {
+        Self::Raw(err)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnEventError {
+    Disconnected,
+    Raw(RawError),
+}
+
+impl From<DisconnectedError> for ConnEventError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<RawError> for ConnEventError {
+    fn from(err: RawError) -> Self {
+        Self::Raw(err)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg(feature = "ble-rssi")]
+pub enum RssiError {
+    Disconnected,
+    Raw(RawError),
+}
+
+#[cfg(feature = "ble-rssi")]
+impl From<DisconnectedError> for RssiError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
+#[cfg(feature = "ble-rssi")]
+impl From<RawError> for RssiError {
+    fn from(err: RawError) -> Self {
+        Self::Raw(err)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[cfg(feature = "ble-peripheral")]
@@ -98,6 +171,16 @@ impl From<RawError> for IgnoreSlaveLatencyError {
     }
 }
 
+#[cfg(feature = "ble-peripheral")]
+impl From<OptionError> for IgnoreSlaveLatencyError {
+    fn from(err: OptionError) -> Self {
+        match err {
+            OptionError::Disconnected => Self::Disconnected,
+            OptionError::Raw(err) => Self::Raw(err),
+        }
+    }
+}
+
 #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
 #[derive(Debug, Clone, Copy)]
 pub enum DataLengthUpdateError {
@@ -114,8 +197,14 @@ impl From<DisconnectedError> for DataLengthUpdateError {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PhyUpdateError {
     Disconnected,
+    /// The PHY update procedure failed, for example because the peer doesn't support it.
+    Status(HciStatus),
+    /// The procedure completed with a PHY this softdevice doesn't support.
+    UnknownPhy,
     Raw(RawError),
 }
 
@@ -177,6 +266,157 @@ impl From<RawError> for EncryptError {
     }
 }
 
+/// A change in the state of the link, see [`Connection::wait_for_change`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum LinkEvent {
+    /// The connection parameters changed.
+    ConnParams(ble_gap_conn_params_t),
+    /// A PHY update procedure completed successfully.
+    Phy { tx: Phy, rx: Phy },
+    /// A data length update procedure completed.
+    #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+    DataLength(raw::ble_gap_data_length_params_t),
+    /// The security mode changed.
+    SecurityMode(SecurityMode),
+    /// A new RSSI measurement is available. This is the filtered value returned by [`Connection::rssi`].
+    #[cfg(feature = "ble-rssi")]
+    Rssi(i8),
+    /// The ATT MTU changed.
+    #[cfg(feature = "ble-gatt")]
+    AttMtu(u16),
+}
+
+/// Changes in the state of the link since subscribing, see [`Connection::subscribe_link_events`].
+///
+/// Changes are coalesced per kind: if a kind changes several times before [`next`][Self::next] is
+/// called, only its latest value is returned, but no kind of change is ever missed. Changes of
+/// different kinds are returned in the order they last happened.
+pub struct LinkEventSubscriber {
+    conn: Connection,
+    events: &'static LinkEvents,
+    seen: [u32; LINK_EVENT_KINDS],
+}
+
+impl LinkEventSubscriber {
+    /// Wait for the next change.
+    ///
+    /// Returns an error once the connection is disconnected.
+    pub async fn next(&mut self) -> Result<LinkEvent, DisconnectedError> {
+        poll_fn(|cx| {
+            if let Err(err) = self.conn.with_state(|state| state.check_connected()) {
+                return Poll::Ready(Err(err));
+            }
+            self.events.poll(&mut self.seen, cx).map(Ok)
+        })
+        .await
+    }
+}
+
+/// A PPI task triggered at the start of connection events, see [`Connection::start_conn_evt_trigger`].
+///
+/// Owns the PPI channel used by the softdevice. Dropping it stops the trigger and frees the channel,
+/// [`stop`](Self::stop) stops the trigger and hands the channel back.
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+pub struct ConnEventTrigger {
+    conn: Connection,
+    channel: Option<ppi::Channel>,
+}
+
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+impl ConnEventTrigger {
+    /// The connection whose events trigger the task.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Stop triggering the task and get the PPI channel back.
+    pub fn stop(mut self) -> ppi::Channel {
+        self.stop_trigger();
+        unwrap!(self.channel.take())
+    }
+
+    fn stop_trigger(&self) {
+        // Once disconnected, the softdevice doesn't use the channel anymore.
+        let conn_handle = match self.conn.with_state(|state| state.check_connected()) {
+            Ok(conn_handle) => conn_handle,
+            Err(_) => return,
+        };
+        let ret = unsafe { raw::sd_ble_gap_conn_evt_trigger_stop(conn_handle) };
+        if let Err(_err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_conn_evt_trigger_stop err {:?}", _err);
+        }
+    }
+}
+
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+impl Drop for ConnEventTrigger {
+    fn drop(&mut self) {
+        if self.channel.is_some() {
+            self.stop_trigger();
+        }
+    }
+}
+
+/// A single RSSI measurement.
+#[cfg(feature = "ble-rssi")]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RssiSample {
+    /// Received signal strength, in dBm.
+    pub rssi: i8,
+    /// Data channel index the measurement was done on, 0 to 36.
+    pub channel: u8,
+}
+
+#[cfg(feature = "ble-rssi-channel-stats")]
+const DATA_CHANNEL_COUNT: usize = 37;
+
+/// RSSI statistics for each data channel of a connection, see [`Connection::rssi_channel_stats`].
+///
+/// These take about 110 bytes of RAM per connection, so they need the `ble-rssi-channel-stats` feature.
+#[cfg(feature = "ble-rssi-channel-stats")]
+#[derive(Debug, Clone, Copy)]
+pub struct RssiChannelStats {
+    count: [u16; DATA_CHANNEL_COUNT],
+    average: [i8; DATA_CHANNEL_COUNT],
+}
+
+#[cfg(feature = "ble-rssi-channel-stats")]
+impl RssiChannelStats {
+    const fn new() -> Self {
+        Self {
+            count: [0; DATA_CHANNEL_COUNT],
+            average: [0; DATA_CHANNEL_COUNT],
+        }
+    }
+
+    /// Number of measurements reported on the data channel.
+    pub fn count(&self, channel: u8) -> u16 {
+        self.count.get(usize::from(channel)).copied().unwrap_or(0)
+    }
+
+    /// Filtered RSSI of the data channel, or `None` if no measurement was reported on it.
+    pub fn average(&self, channel: u8) -> Option<i8> {
+        match self.count(channel) {
+            0 => None,
+            _ => Some(self.average[usize::from(channel)]),
+        }
+    }
+
+    fn add(&mut self, sample: RssiSample) {
+        let i = usize::from(sample.channel);
+        if i >= DATA_CHANNEL_COUNT {
+            return;
+        }
+        self.average[i] = match self.count[i] {
+            0 => sample.rssi,
+            _ => (((self.average[i] as i16) * 7 + (sample.rssi as i16)) / 8) as i8,
+        };
+        self.count[i] = self.count[i].saturating_add(1);
+    }
+}
+
 // Highest ever the softdevice can support.
 pub(crate) const CONNS_MAX: usize = 20;
 
@@ -242,14 +482,20 @@ pub(crate) struct ConnectionState {
     pub security_mode: SecurityMode,
 
     pub conn_params: ble_gap_conn_params_t,
+    pub conn_params_policy: Option<&'static dyn ConnParamsPolicy>,
+    pub phy_policy: Option<&'static dyn PhyPolicy>,
 
     #[cfg(feature = "ble-rssi")]
     pub rssi: Option<i8>,
+    #[cfg(feature = "ble-rssi-channel-stats")]
+    pub rssi_channels: RssiChannelStats,
 
     #[cfg(feature = "ble-gatt")]
     pub att_mtu: u16, // Effective ATT_MTU size (in bytes).
     #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
     pub data_length_effective: u8, // Effective data length (in bytes).
+    #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+    pub data_length_params: raw::ble_gap_data_length_params_t, // Effective data length params.
 
     #[cfg(feature = "ble-sec")]
     pub security: EncryptionState,
@@ -275,12 +521,23 @@ impl ConnectionState {
                 min_conn_interval: 0,
                 slave_latency: 0,
             },
+            conn_params_policy: None,
+            phy_policy: None,
             #[cfg(feature = "ble-rssi")]
             rssi: None,
+            #[cfg(feature = "ble-rssi-channel-stats")]
+            rssi_channels: RssiChannelStats::new(),
             #[cfg(feature = "ble-gatt")]
             att_mtu: 0,
             #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
             data_length_effective: 0,
+            #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+            data_length_params: raw::ble_gap_data_length_params_t {
+                max_tx_octets: 0,
+                max_rx_octets: 0,
+                max_tx_time_us: 0,
+                max_rx_time_us: 0,
+            },
             #[cfg(feature = "ble-sec")]
             security: NEW_ENCRYPTION_STATE,
         }
@@ -344,10 +601,29 @@ impl ConnectionState {
         crate::ble::gatt_server::portal(conn_handle).call(ble_evt);
         #[cfg(feature = "ble-l2cap")]
         crate::ble::l2cap::portal(conn_handle).call(ble_evt);
+        link_events(conn_handle).disconnected();
+        #[cfg(feature = "ble-rssi")]
+        rssi_samples(conn_handle).disconnected();
+        conn_params_portal(conn_handle).call(ble_evt);
+        phy_portal(conn_handle).call(ble_evt);
+        #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+        data_length_portal(conn_handle).call(ble_evt);
 
         trace!("conn {:?}: disconnected", _index);
     }
 
+    #[cfg(feature = "ble-rssi")]
+    pub(crate) fn on_rssi_changed(&mut self, sample: RssiSample) -> i8 {
+        let rssi = match self.rssi {
+            None => sample.rssi,
+            Some(old_rssi) => (((old_rssi as i16) * 7 + (sample.rssi as i16)) / 8) as i8,
+        };
+        self.rssi = Some(rssi);
+        #[cfg(feature = "ble-rssi-channel-stats")]
+        self.rssi_channels.add(sample);
+        rssi
+    }
+
     pub(crate) fn keyset(&mut self) -> raw::ble_gap_sec_keyset_t {
         #[cfg(feature = "ble-sec")]
         return raw::ble_gap_sec_keyset_t {
@@ -471,15 +747,26 @@ impl Connection {
                 disconnecting: false,
 
                 conn_params,
+                conn_params_policy: None,
+                phy_policy: None,
 
                 #[cfg(feature = "ble-rssi")]
                 rssi: None,
+                #[cfg(feature = "ble-rssi-channel-stats")]
+                rssi_channels: RssiChannelStats::new(),
 
                 #[cfg(feature = "ble-gatt")]
                 att_mtu: raw::BLE_GATT_ATT_MTU_DEFAULT as _,
 
                 #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
                 data_length_effective: BLE_GAP_DATA_LENGTH_DEFAULT,
+                #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+                data_length_params: raw::ble_gap_data_length_params_t {
+                    max_tx_octets: BLE_GAP_DATA_LENGTH_DEFAULT as u16,
+                    max_rx_octets: BLE_GAP_DATA_LENGTH_DEFAULT as u16,
+                    max_tx_time_us: BLE_GAP_DATA_TIME_DEFAULT,
+                    max_rx_time_us: BLE_GAP_DATA_TIME_DEFAULT,
+                },
 
                 #[cfg(feature = "ble-sec")]
                 security: NEW_ENCRYPTION_STATE,
@@ -509,25 +796,105 @@ impl Connection {
     }
 
     /// Start measuring RSSI on this connection.
-    #[cfg(feature = "ble-rssi")]
+    ///
+    /// A measurement is reported every time the RSSI changes. Use [`start_rssi_with`](Self::start_rssi_with)
+    /// to report only significant changes.
+    #[cfg(all(feature = "ble-rssi", not(feature = "s122")))]
     pub fn start_rssi(&self) {
-        if let Ok(conn_handle) = self.with_state(|state| state.check_connected()) {
-            let ret = unsafe { raw::sd_ble_gap_rssi_start(conn_handle, 0, 0) };
-            if let Err(err) = RawError::convert(ret) {
-                warn!("sd_ble_gap_rssi_start err {:?}", err);
+        let _ = self.start_rssi_with(0, 0);
+    }
+
+    /// Start measuring RSSI on this connection.
+    ///
+    /// A measurement is reported once the RSSI changed by at least `threshold_dbm` for more than
+    /// `skip_count` consecutive samples. With a threshold of [`raw::BLE_GAP_RSSI_THRESHOLD_INVALID`],
+    /// nothing is reported and the RSSI can only be read with [`rssi_sample`](Self::rssi_sample).
+    ///
+    /// This resets the filtered RSSI and the per channel statistics.
+    #[cfg(all(feature = "ble-rssi", not(feature = "s122")))]
+    pub fn start_rssi_with(&self, threshold_dbm: u8, skip_count: u8) -> Result<(), RssiError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let ret = unsafe { raw::sd_ble_gap_rssi_start(conn_handle, threshold_dbm, skip_count) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_rssi_start err {:?}", err);
+            return Err(err.into());
+        }
+        self.with_state(|state| {
+            state.rssi = None;
+            #[cfg(feature = "ble-rssi-channel-stats")]
+            {
+                state.rssi_channels = RssiChannelStats::new();
             }
+        });
+        Ok(())
+    }
+
+    /// Stop measuring RSSI on this connection.
+    ///
+    /// The per channel statistics are kept until RSSI measurement is started again.
+    #[cfg(all(feature = "ble-rssi", not(feature = "s122")))]
+    pub fn stop_rssi(&self) -> Result<(), RssiError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let ret = unsafe { raw::sd_ble_gap_rssi_stop(conn_handle) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_rssi_stop err {:?}", err);
+            return Err(err.into());
         }
+        self.with_state(|state| state.rssi = None);
+        Ok(())
     }
 
     /// Get the connection's RSSI.
     ///
-    /// This will return None if `start_rssi` has not been called yet, or if
-    /// no measurement has been done yet.
+    /// This is a filtered value, updated with every reported measurement. It will return None if
+    /// `start_rssi` has not been called yet, or if no measurement has been reported yet.
     #[cfg(feature = "ble-rssi")]
     pub fn rssi(&self) -> Option<i8> {
         self.with_state(|state| state.rssi)
     }
 
+    /// Get the unfiltered RSSI measured during the last connection event.
+    ///
+    /// Returns [`RawError::NotFound`] if no measurement has been done yet since RSSI measurement was started.
+    #[cfg(feature = "ble-rssi")]
+    pub fn rssi_sample(&self) -> Result<RssiSample, RssiError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let mut rssi = 0;
+        let mut channel = 0;
+        let ret = unsafe { raw::sd_ble_gap_rssi_get(conn_handle, &mut rssi, &mut channel) };
+        RawError::convert(ret)?;
+        Ok(RssiSample { rssi, channel })
+    }
+
+    /// Get the RSSI statistics of each data channel, built from the reported measurements.
+    #[cfg(feature = "ble-rssi-channel-stats")]
+    pub fn rssi_channel_stats(&self) -> RssiChannelStats {
+        self.with_state(|state| state.rssi_channels)
+    }
+
+    /// Wait for the next reported RSSI measurement.
+    ///
+    /// How often measurements are reported depends on the parameters given to
+    /// [`start_rssi_with`](Self::start_rssi_with). Unlike [`LinkEvent::Rssi`], the returned
+    /// measurement is unfiltered.
+    ///
+    /// Several tasks can wait for measurements on the same connection, each of them gets the next
+    /// one. Measurements reported while no task is waiting are lost. Beyond [`LINK_EVENT_WAITERS_MAX`]
+    /// tasks, waiting tasks are woken up spuriously.
+    #[cfg(feature = "ble-rssi")]
+    pub async fn wait_rssi_change(&self) -> Result<RssiSample, DisconnectedError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let samples = rssi_samples(conn_handle);
+        let seq = samples.seq();
+        poll_fn(|cx| {
+            if let Err(err) = self.with_state(|state| state.check_connected()) {
+                return Poll::Ready(Err(err));
+            }
+            samples.poll(seq, cx).map(Ok)
+        })
+        .await
+    }
+
     /// Get the currently active connection params.
     pub fn conn_params(&self) -> ble_gap_conn_params_t {
         with_state(self.index, |s| s.conn_params)
@@ -548,6 +915,31 @@ impl Connection {
         with_state(self.index, |s| s.security.handler)
     }
 
+    /// Get the connection parameter policy of this connection.
+    pub fn conn_params_policy(&self) -> Option<&'static dyn ConnParamsPolicy> {
+        with_state(self.index, |s| s.conn_params_policy)
+    }
+
+    /// Set the connection parameter policy of this connection.
+    ///
+    /// For central connections, it is used to answer connection parameter update requests from the peripheral.
+    /// For peripheral connections, it is used by [`conn_params::negotiate`](super::conn_params::negotiate).
+    pub fn set_conn_params_policy(&self, policy: Option<&'static dyn ConnParamsPolicy>) {
+        with_state(self.index, |s| s.conn_params_policy = policy)
+    }
+
+    /// Get the PHY policy of this connection.
+    pub fn phy_policy(&self) -> Option<&'static dyn PhyPolicy> {
+        with_state(self.index, |s| s.phy_policy)
+    }
+
+    /// Set the PHY policy of this connection, used to answer PHY update requests from the peer.
+    ///
+    /// If `None`, the default policy set with [`phy::set_default_policy`](super::phy::set_default_policy) is used.
+    pub fn set_phy_policy(&self, policy: Option<&'static dyn PhyPolicy>) {
+        with_state(self.index, |s| s.phy_policy = policy)
+    }
+
     /// Set the connection params.
     ///
     /// Note that this just initiates the connection param change, it does not wait for completion.
@@ -568,6 +960,55 @@ impl Connection {
         Ok(())
     }
 
+    /// Set the connection params, and wait for the procedure to complete.
+    ///
+    /// Returns the connection params in use once it has completed. These might differ from
+    /// the requested ones, for example if the central rejected the request from a peripheral.
+    ///
+    /// The peer is not required to answer in a timely manner, so consider using a timeout.
+    ///
+    /// Concurrent calls on the same connection wait for each other.
+    pub async fn set_conn_params_and_wait(
+        &self,
+        conn_params: ble_gap_conn_params_t,
+    ) -> Result<ble_gap_conn_params_t, SetConnParamsError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let _lock = CONN_PARAMS_LOCKS[conn_handle as usize].lock().await;
+        self.set_conn_params(conn_params)?;
+
+        conn_params_portal(conn_handle)
+            .wait_once(|ble_evt| unsafe {
+                match (*ble_evt).header.evt_id as u32 {
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => Err(SetConnParamsError::Disconnected),
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONN_PARAM_UPDATE => {
+                        let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+                        Ok(gap_evt.params.conn_param_update.conn_params)
+                    }
+                    e => panic!("unexpected event {}", e),
+                }
+            })
+            .await
+    }
+
+    /// Set the radio transmit power used on this connection.
+    ///
+    /// A new connection initially uses the transmit power of the advertiser or initiator that created it.
+    pub fn set_tx_power(&self, tx_power: TxPower) -> Result<(), SetTxPowerError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let ret = unsafe {
+            raw::sd_ble_gap_tx_power_set(
+                raw::BLE_GAP_TX_POWER_ROLES_BLE_GAP_TX_POWER_ROLE_CONN as _,
+                conn_handle,
+                tx_power as i8,
+            )
+        };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_tx_power_set err {:?}", err);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
     /// Temporarily ignore slave latency for peripehral connections.
     ///
     /// "Slave latency" is a setting in the conn params that allows the peripheral
@@ -585,28 +1026,34 @@ impl Connection {
     /// This only works on peripheral connections.
     #[cfg(feature = "ble-peripheral")]
     pub fn ignore_slave_latency(&mut self, ignore: bool) -> Result<(), IgnoreSlaveLatencyError> {
-        let conn_handle = self.with_state(|state| state.check_connected())?;
-
-        let mut disable: raw::ble_gap_opt_slave_latency_disable_t = unsafe { core::mem::zeroed() };
-        disable.conn_handle = conn_handle;
-        disable.set_disable(ignore as u8); // 0 or 1
+        options::set_slave_latency_disable(self, ignore)?;
+        Ok(())
+    }
 
-        let ret = unsafe {
-            raw::sd_ble_opt_set(
-                raw::BLE_GAP_OPTS_BLE_GAP_OPT_SLAVE_LATENCY_DISABLE,
-                &raw::ble_opt_t {
-                    gap_opt: raw::ble_gap_opt_t {
-                        slave_latency_disable: disable,
-                    },
-                },
-            )
-        };
-        if let Err(err) = RawError::convert(ret) {
-            warn!("ignore_slave_latency sd_ble_opt_set err {:?}", err);
-            return Err(err.into());
-        }
+    /// Wait for the next change in the state of the link.
+    ///
+    /// Changes are coalesced: only the latest change of each kind is kept, and those that happen
+    /// before this is called are lost. Use [`subscribe_link_events`][Self::subscribe_link_events]
+    /// to get the latest change of every kind, including those that happen while the task is busy.
+    /// The current values can always be read with the corresponding getters, such as
+    /// [`conn_params`][Self::conn_params].
+    ///
+    /// Several tasks can wait for changes on the same connection, each of them gets every change.
+    /// Beyond [`LINK_EVENT_WAITERS_MAX`] tasks, waiting tasks are woken up spuriously.
+    ///
+    /// Returns an error once the connection is disconnected.
+    pub async fn wait_for_change(&self) -> Result<LinkEvent, DisconnectedError> {
+        self.subscribe_link_events()?.next().await
+    }
 
-        Ok(())
+    /// Subscribe to the changes in the state of the link, see [`LinkEventSubscriber`].
+    pub fn subscribe_link_events(&self) -> Result<LinkEventSubscriber, DisconnectedError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        Ok(LinkEventSubscriber {
+            conn: self.clone(),
+            events: link_events(conn_handle),
+            seen: link_events(conn_handle).kind_seq(),
+        })
     }
 
     pub(crate) fn with_state<T>(&self, f: impl FnOnce(&mut ConnectionState) -> T) -> T {
@@ -662,6 +1109,49 @@ impl Connection {
         Ok(())
     }
 
+    /// Initiate a Data Length Update procedure, and wait for it to complete.
+    ///
+    /// Returns the effective data length params.
+    ///
+    /// Returns immediately if `params` are already in effect. Otherwise, no completion is reported if
+    /// the effective params don't change, for example because the peer limits them to the current
+    /// ones, so consider using a timeout.
+    ///
+    /// Concurrent calls on the same connection wait for each other.
+    #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+    pub async fn data_length_update_and_wait(
+        &mut self,
+        params: Option<&raw::ble_gap_data_length_params_t>,
+    ) -> Result<raw::ble_gap_data_length_params_t, DataLengthUpdateError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let _lock = DATA_LENGTH_LOCKS[conn_handle as usize].lock().await;
+        let current = self.with_state(|state| state.check_connected().map(|_| state.data_length_params))?;
+        if let Some(params) = params {
+            if params.max_tx_octets == current.max_tx_octets
+                && params.max_rx_octets == current.max_rx_octets
+                && params.max_tx_time_us == current.max_tx_time_us
+                && params.max_rx_time_us == current.max_rx_time_us
+            {
+                return Ok(current);
+            }
+        }
+
+        self.data_length_update(params)?;
+
+        data_length_portal(conn_handle)
+            .wait_once(|ble_evt| unsafe {
+                match (*ble_evt).header.evt_id as u32 {
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => Err(DataLengthUpdateError::Disconnected),
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_DATA_LENGTH_UPDATE => {
+                        let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+                        Ok(gap_evt.params.data_length_update.effective_params)
+                    }
+                    e => panic!("unexpected event {}", e),
+                }
+            })
+            .await
+    }
+
     /// Send a request to the connected device to change the PHY.
     ///
     /// Note that this just initiates the PHY change, it does not wait for completion.
@@ -682,6 +1172,93 @@ impl Connection {
         Ok(())
     }
 
+    /// Send a request to the connected device to change the PHY, and wait for the procedure to complete.
+    ///
+    /// Returns the `(tx, rx)` PHYs in use once it has completed.
+    ///
+    /// Concurrent calls on the same connection wait for each other.
+    pub async fn phy_update_and_wait(
+        &mut self,
+        tx_phys: PhySet,
+        rx_phys: PhySet,
+    ) -> Result<(Phy, Phy), PhyUpdateError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let _lock = PHY_LOCKS[conn_handle as usize].lock().await;
+        self.phy_update(tx_phys, rx_phys)?;
+
+        phy_portal(conn_handle)
+            .wait_once(|ble_evt| unsafe {
+                match (*ble_evt).header.evt_id as u32 {
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => Err(PhyUpdateError::Disconnected),
+                    raw::BLE_GAP_EVTS_BLE_GAP_EVT_PHY_UPDATE => {
+                        let gap_evt = get_union_field(ble_evt, &(*ble_evt).evt.gap_evt);
+                        let params = gap_evt.params.phy_update;
+                        let status = HciStatus::new(params.status);
+                        if status != HciStatus::SUCCESS {
+                            return Err(PhyUpdateError::Status(status));
+                        }
+                        match (Phy::from_raw(params.tx_phy), Phy::from_raw(params.rx_phy)) {
+                            (Some(tx), Some(rx)) => Ok((tx, rx)),
+                            _ => Err(PhyUpdateError::UnknownPhy),
+                        }
+                    }
+                    e => panic!("unexpected event {}", e),
+                }
+            })
+            .await
+    }
+
+    /// Get the counter of the next connection event.
+    ///
+    /// The counter starts at zero on the first connection event and is incremented on each connection
+    /// event, whether or not it actually takes place, so the next event to happen may have a higher
+    /// counter when slave latency is used or when another role has priority.
+    pub fn next_conn_evt_counter(&self) -> Result<u16, ConnEventError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let mut counter = 0;
+        let ret = unsafe { raw::sd_ble_gap_next_conn_evt_counter_get(conn_handle, &mut counter) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_next_conn_evt_counter_get err {:?}", err);
+            return Err(err.into());
+        }
+        Ok(counter)
+    }
+
+    /// Trigger a task through PPI at the start of connection events.
+    ///
+    /// `task` is triggered on the connection event with counter `start_counter`, then every
+    /// `period_in_events` connection events (1 to 32767). `start_counter` must not be in the past, see
+    /// [`next_conn_evt_counter`](Self::next_conn_evt_counter). On peripheral connections with slave
+    /// latency, `period_in_events` should be a multiple of `slave_latency + 1` to keep the power savings.
+    ///
+    /// The softdevice uses `ppi_channel` until the returned [`ConnEventTrigger`] is stopped or dropped,
+    /// so it must not be connected to anything else until then. If starting fails, the channel is freed.
+    #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+    pub fn start_conn_evt_trigger(
+        &self,
+        ppi_channel: ppi::Channel,
+        task: ppi::Task,
+        start_counter: u16,
+        period_in_events: u16,
+    ) -> Result<ConnEventTrigger, ConnEventError> {
+        let conn_handle = self.with_state(|state| state.check_connected())?;
+        let params = raw::ble_gap_conn_event_trigger_t {
+            ppi_ch_id: ppi_channel.number(),
+            task_endpoint: task.address(),
+            conn_evt_counter_start: start_counter,
+            period_in_events,
+        };
+        let ret = unsafe { raw::sd_ble_gap_conn_evt_trigger_start(conn_handle, &params) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ble_gap_conn_evt_trigger_start err {:?}", err);
+            return Err(err.into());
+        }
+        Ok(ConnEventTrigger {
+            conn: self.clone(),
+            channel: Some(ppi_channel),
+        })
+    }
+
     #[cfg(feature = "ble-central")]
     /// Send a pairing request to the peripheral.
     pub fn request_pairing(&self) -> Result<(), AuthenticateError> {
@@ -835,6 +1412,16 @@ fn allocate_index<T>(f: impl FnOnce(u8, &mut ConnectionState) -> T) -> Result<T,
     }
 }
 
+/// Whether any connection is established, or any `Connection` instance is alive.
+pub(crate) fn any_connection_active() -> bool {
+    unsafe {
+        STATES.iter().any(|s| {
+            let state = &*s.get();
+            state.refcount != 0 || state.conn_handle.is_connected()
+        })
+    }
+}
+
 // conn_handle -> index mapping. Used to make stuff go faster
 const INDEX_NONE: Cell<Option<u8>> = Cell::new(None);
 static mut INDEX_BY_HANDLE: [Cell<Option<u8>>; CONNS_MAX] = [INDEX_NONE; CONNS_MAX];
@@ -842,3 +1429,197 @@ static mut INDEX_BY_HANDLE: [Cell<Option<u8>>; CONNS_MAX] = [INDEX_NONE; CONNS_M
 fn index_by_handle(conn_handle: u16) -> &'static Cell<Option<u8>> {
     unsafe { &INDEX_BY_HANDLE[conn_handle as usize] }
 }
+
+/// Number of tasks that can wait for link events on a connection at the same time without being
+/// woken up spuriously, see [`Connection::wait_for_change`].
+pub const LINK_EVENT_WAITERS_MAX: usize = 4;
+
+const LINK_EVENT_KINDS: usize = 6;
+
+impl LinkEvent {
+    fn kind(&self) -> usize {
+        match self {
+            Self::ConnParams(_) => 0,
+            Self::Phy { .. } => 1,
+            #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+            Self::DataLength(_) => 2,
+            Self::SecurityMode(_) => 3,
+            #[cfg(feature = "ble-rssi")]
+            Self::Rssi(_) => 4,
+            #[cfg(feature = "ble-gatt")]
+            Self::AttMtu(_) => 5,
+        }
+    }
+}
+
+/// Latest link event of each kind of a connection, shared by all the subscribers.
+pub(crate) struct LinkEvents {
+    state: Mutex<CriticalSectionRawMutex, RefCell<LinkEventsState>>,
+}
+
+struct LinkEventsState {
+    // Incremented on every event.
+    seq: u32,
+    // Value of `seq` when the latest event of each kind was stored, so subscribers can tell
+    // which ones they haven't seen yet, and in which order they happened.
+    kind_seq: [u32; LINK_EVENT_KINDS],
+    latest: [Option<LinkEvent>; LINK_EVENT_KINDS],
+    wakers: MultiWakerRegistration<LINK_EVENT_WAITERS_MAX>,
+}
+
+impl LinkEvents {
+    const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(LinkEventsState {
+                seq: 0,
+                kind_seq: [0; LINK_EVENT_KINDS],
+                latest: [None; LINK_EVENT_KINDS],
+                wakers: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+
+    pub(crate) fn call(&self, event: LinkEvent) {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let kind = event.kind();
+            state.seq = state.seq.wrapping_add(1);
+            state.kind_seq[kind] = state.seq;
+            state.latest[kind] = Some(event);
+            state.wakers.wake();
+        })
+    }
+
+    /// Wake up the subscribers so they notice the disconnection.
+    pub(crate) fn disconnected(&self) {
+        self.state.lock(|state| state.borrow_mut().wakers.wake())
+    }
+
+    fn kind_seq(&self) -> [u32; LINK_EVENT_KINDS] {
+        self.state.lock(|state| state.borrow().kind_seq)
+    }
+
+    /// Get the oldest of the latest events of each kind that changed since `seen`, and mark it seen.
+    fn poll(&self, seen: &mut [u32; LINK_EVENT_KINDS], cx: &mut Context<'_>) -> Poll<LinkEvent> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let seq = state.seq;
+            let oldest = (0..LINK_EVENT_KINDS)
+                .filter(|&kind| state.kind_seq[kind] != seen[kind])
+                .max_by_key(|&kind| seq.wrapping_sub(state.kind_seq[kind]));
+            match oldest.and_then(|kind| Some((kind, state.latest[kind]?))) {
+                Some((kind, event)) => {
+                    seen[kind] = state.kind_seq[kind];
+                    Poll::Ready(event)
+                }
+                None => {
+                    state.wakers.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+// Link events by conn_handle.
+const LINK_EVENTS_NEW: LinkEvents = LinkEvents::new();
+static LINK_EVENTS: [LinkEvents; CONNS_MAX] = [LINK_EVENTS_NEW; CONNS_MAX];
+
+pub(crate) fn link_events(conn_handle: u16) -> &'static LinkEvents {
+    &LINK_EVENTS[conn_handle as usize]
+}
+
+/// Last RSSI measurement of a connection, broadcast to all the waiting tasks.
+#[cfg(feature = "ble-rssi")]
+pub(crate) struct RssiSamples {
+    state: Mutex<CriticalSectionRawMutex, RefCell<RssiSamplesState>>,
+}
+
+#[cfg(feature = "ble-rssi")]
+struct RssiSamplesState {
+    // Incremented on every measurement, so waiters can tell whether they've seen it.
+    seq: u32,
+    sample: RssiSample,
+    wakers: MultiWakerRegistration<LINK_EVENT_WAITERS_MAX>,
+}
+
+#[cfg(feature = "ble-rssi")]
+impl RssiSamples {
+    const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(RssiSamplesState {
+                seq: 0,
+                sample: RssiSample { rssi: 0, channel: 0 },
+                wakers: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+
+    pub(crate) fn call(&self, sample: RssiSample) {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            state.seq = state.seq.wrapping_add(1);
+            state.sample = sample;
+            state.wakers.wake();
+        })
+    }
+
+    /// Wake up the waiting tasks so they notice the disconnection.
+    pub(crate) fn disconnected(&self) {
+        self.state.lock(|state| state.borrow_mut().wakers.wake())
+    }
+
+    fn seq(&self) -> u32 {
+        self.state.lock(|state| state.borrow().seq)
+    }
+
+    /// Get the last measurement if there was one since `seq`.
+    fn poll(&self, seq: u32, cx: &mut Context<'_>) -> Poll<RssiSample> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            if state.seq != seq {
+                Poll::Ready(state.sample)
+            } else {
+                state.wakers.register(cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+}
+
+// RSSI measurements by conn_handle.
+#[cfg(feature = "ble-rssi")]
+const RSSI_SAMPLES_NEW: RssiSamples = RssiSamples::new();
+#[cfg(feature = "ble-rssi")]
+static RSSI_SAMPLES: [RssiSamples; CONNS_MAX] = [RSSI_SAMPLES_NEW; CONNS_MAX];
+
+#[cfg(feature = "ble-rssi")]
+pub(crate) fn rssi_samples(conn_handle: u16) -> &'static RssiSamples {
+    &RSSI_SAMPLES[conn_handle as usize]
+}
+
+// Completion of GAP procedures by conn_handle.
+const PORTAL_NEW: Portal<*const raw::ble_evt_t> = Portal::new();
+static CONN_PARAMS_PORTALS: [Portal<*const raw::ble_evt_t>; CONNS_MAX] = [PORTAL_NEW; CONNS_MAX];
+static PHY_PORTALS: [Portal<*const raw::ble_evt_t>; CONNS_MAX] = [PORTAL_NEW; CONNS_MAX];
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+static DATA_LENGTH_PORTALS: [Portal<*const raw::ble_evt_t>; CONNS_MAX] = [PORTAL_NEW; CONNS_MAX];
+
+pub(crate) fn conn_params_portal(conn_handle: u16) -> &'static Portal<*const raw::ble_evt_t> {
+    &CONN_PARAMS_PORTALS[conn_handle as usize]
+}
+pub(crate) fn phy_portal(conn_handle: u16) -> &'static Portal<*const raw::ble_evt_t> {
+    &PHY_PORTALS[conn_handle as usize]
+}
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+pub(crate) fn data_length_portal(conn_handle: u16) -> &'static Portal<*const raw::ble_evt_t> {
+    &DATA_LENGTH_PORTALS[conn_handle as usize]
+}
+
+// Held while waiting for a GAP procedure to complete, so that only one task at a time waits on its portal.
+type ProcedureLock = embassy_sync::mutex::Mutex<CriticalSectionRawMutex, ()>;
+const PROCEDURE_LOCK_NEW: ProcedureLock = ProcedureLock::new(());
+static CONN_PARAMS_LOCKS: [ProcedureLock; CONNS_MAX] = [PROCEDURE_LOCK_NEW; CONNS_MAX];
+static PHY_LOCKS: [ProcedureLock; CONNS_MAX] = [PROCEDURE_LOCK_NEW; CONNS_MAX];
+#[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
+static DATA_LENGTH_LOCKS: [ProcedureLock; CONNS_MAX] = [PROCEDURE_LOCK_NEW; CONNS_MAX];
@@ -45,6 +45,8 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
             connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| {
                 state.conn_params = conn_params;
             });
+            connection::link_events(gap_evt.conn_handle).call(LinkEvent::ConnParams(conn_params));
+            connection::conn_params_portal(gap_evt.conn_handle).call(ble_evt);
         }
         #[cfg(feature = "ble-central")]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_CONN_PARAM_UPDATE_REQUEST => {
@@ -59,10 +61,7 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                 conn_params.slave_latency,
             );
 
-            let ret = raw::sd_ble_gap_conn_param_update(conn_handle, &conn_params);
-            if let Err(err) = RawError::convert(ret) {
-                warn!("sd_ble_gap_conn_param_update err {:?}", err);
-            }
+            conn_params::on_update_request(conn_handle, &conn_params);
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_TIMEOUT => {
             trace!("on_timeout conn_handle={:?}", gap_evt.conn_handle);
@@ -86,6 +85,10 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
             trace!("central on_adv_report");
             central::SCAN_PORTAL.call(ble_evt);
         }
+        #[cfg(any(feature = "s132", feature = "s140"))]
+        raw::BLE_GAP_EVTS_BLE_GAP_EVT_QOS_CHANNEL_SURVEY_REPORT => {
+            channel_survey::PORTAL.call(ble_evt);
+        }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_PHY_UPDATE_REQUEST => {
             let peer_preferred_phys = gap_evt.params.phy_update_request.peer_preferred_phys;
             let conn_handle = gap_evt.conn_handle;
@@ -97,27 +100,25 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                 peer_preferred_phys.tx_phys
             );
 
-            let phys = raw::ble_gap_phys_t {
-                rx_phys: peer_preferred_phys.rx_phys,
-                tx_phys: peer_preferred_phys.tx_phys,
-            };
-
-            let ret = raw::sd_ble_gap_phy_update(conn_handle, &phys as *const raw::ble_gap_phys_t);
-
-            if let Err(_err) = RawError::convert(ret) {
-                warn!("sd_ble_gap_phy_update err {:?}", _err);
-            }
+            phy::on_update_request(conn_handle, peer_preferred_phys);
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_PHY_UPDATE => {
-            let _phy_update = gap_evt.params.phy_update;
+            let phy_update = gap_evt.params.phy_update;
 
             trace!(
                 "on_phy_update conn_handle={:?} status={:?} rx_phy={:?} tx_phy={:?}",
                 gap_evt.conn_handle,
-                _phy_update.status,
-                _phy_update.rx_phy,
-                _phy_update.tx_phy
+                phy_update.status,
+                phy_update.rx_phy,
+                phy_update.tx_phy
             );
+
+            if u32::from(phy_update.status) == raw::BLE_HCI_STATUS_CODE_SUCCESS {
+                if let (Some(tx), Some(rx)) = (Phy::from_raw(phy_update.tx_phy), Phy::from_raw(phy_update.rx_phy)) {
+                    connection::link_events(gap_evt.conn_handle).call(LinkEvent::Phy { tx, rx });
+                }
+            }
+            connection::phy_portal(gap_evt.conn_handle).call(ble_evt);
         }
         #[cfg(any(feature = "s113", feature = "s132", feature = "s140"))]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_DATA_LENGTH_UPDATE_REQUEST => {
@@ -143,7 +144,10 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
 
             connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| {
                 state.data_length_effective = effective_params.max_tx_octets as u8;
+                state.data_length_params = effective_params;
             });
+            connection::link_events(gap_evt.conn_handle).call(LinkEvent::DataLength(effective_params));
+            connection::data_length_portal(gap_evt.conn_handle).call(ble_evt);
 
             debug!(
                 "on_data_length_update conn_handle={:?} max_rx_octets={:?} max_rx_time_us={:?} max_tx_octets={:?} max_tx_time_us={:?}",
@@ -156,13 +160,14 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
         }
         #[cfg(feature = "ble-rssi")]
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_RSSI_CHANGED => {
-            let new_rssi = gap_evt.params.rssi_changed.rssi;
-            connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| {
-                state.rssi = match state.rssi {
-                    None => Some(new_rssi),
-                    Some(old_rssi) => Some((((old_rssi as i16) * 7 + (new_rssi as i16)) / 8) as i8),
-                };
-            });
+            let sample = RssiSample {
+                rssi: gap_evt.params.rssi_changed.rssi,
+                channel: gap_evt.params.rssi_changed.ch_index,
+            };
+            let rssi =
+                connection::with_state_by_conn_handle(gap_evt.conn_handle, |state| state.on_rssi_changed(sample));
+            connection::link_events(gap_evt.conn_handle).call(LinkEvent::Rssi(rssi));
+            connection::rssi_samples(gap_evt.conn_handle).call(sample);
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_SEC_PARAMS_REQUEST => {
             let params = &gap_evt.params.sec_params_request;
@@ -291,13 +296,15 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
                 params.conn_sec.encr_key_size
             );
             if let Some(conn) = Connection::from_handle(gap_evt.conn_handle) {
-                conn.with_state(|state| {
+                let security_mode = conn.with_state(|state| {
                     state.security_mode = SecurityMode::try_from_raw(params.conn_sec.sec_mode).unwrap_or_default();
                     #[cfg(feature = "ble-sec")]
                     if let Some(handler) = state.security.handler {
                         handler.on_security_update(&conn, state.security_mode);
                     }
+                    state.security_mode
                 });
+                connection::link_events(gap_evt.conn_handle).call(LinkEvent::SecurityMode(security_mode));
             }
         }
         raw::BLE_GAP_EVTS_BLE_GAP_EVT_AUTH_STATUS => {
@@ -371,7 +378,6 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
         // BLE_GAP_EVTS_BLE_GAP_EVT_LESC_DHKEY_REQUEST (LESC key calculation)
         // BLE_GAP_EVTS_BLE_GAP_EVT_RSSI_CHANGED
         // BLE_GAP_EVTS_BLE_GAP_EVT_SCAN_REQ_REPORT
-        // BLE_GAP_EVTS_BLE_GAP_EVT_QOS_CHANNEL_SURVEY_REPORT
         _ => {}
     }
 }
@@ -440,6 +446,56 @@ pub fn set_whitelist(sd: &Softdevice, addrs: &[Address]) -> Result<(), RawError>
     RawError::convert(ret)
 }
 
+/// Set the GAP device name, and who can change it through the Device Name characteristic.
+///
+/// The name length is limited by the `max_len` set in [`Config::gap_device_name`](crate::Config::gap_device_name).
+pub fn set_device_name(_sd: &Softdevice, name: &[u8], write_perm: SecurityMode) -> Result<(), RawError> {
+    let write_perm = write_perm.into_raw();
+    let ret = unsafe { raw::sd_ble_gap_device_name_set(&write_perm, name.as_ptr(), name.len() as u16) };
+    RawError::convert(ret)
+}
+
+/// Get the GAP device name, which might have been changed by a peer.
+///
+/// Returns the part of `buf` holding the name, or [`RawError::DataSize`] if `buf` is too small.
+pub fn get_device_name<'a>(_sd: &Softdevice, buf: &'a mut [u8]) -> Result<&'a [u8], RawError> {
+    let mut len = buf.len().min(u16::MAX as usize) as u16;
+    let ret = unsafe { raw::sd_ble_gap_device_name_get(buf.as_mut_ptr(), &mut len) };
+    RawError::convert(ret)?;
+    if usize::from(len) > buf.len() {
+        return Err(RawError::DataSize);
+    }
+    Ok(&buf[..usize::from(len)])
+}
+
+pub fn set_appearance(_sd: &Softdevice, appearance: Appearance) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_ble_gap_appearance_set(appearance.to_u16()) };
+    RawError::convert(ret)
+}
+
+pub fn get_appearance(_sd: &Softdevice) -> Result<Appearance, RawError> {
+    let mut appearance = 0;
+    let ret = unsafe { raw::sd_ble_gap_appearance_get(&mut appearance) };
+    RawError::convert(ret)?;
+    Ok(Appearance::from_u16(appearance))
+}
+
+/// Set the Peripheral Preferred Connection Parameters exposed in the GAP service.
+#[cfg(feature = "ble-peripheral")]
+pub fn set_ppcp(_sd: &Softdevice, conn_params: &raw::ble_gap_conn_params_t) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_ble_gap_ppcp_set(conn_params) };
+    RawError::convert(ret)
+}
+
+/// Get the Peripheral Preferred Connection Parameters exposed in the GAP service.
+#[cfg(feature = "ble-peripheral")]
+pub fn get_ppcp(_sd: &Softdevice) -> Result<raw::ble_gap_conn_params_t, RawError> {
+    let mut conn_params: raw::ble_gap_conn_params_t = unsafe { core::mem::zeroed() };
+    let ret = unsafe { raw::sd_ble_gap_ppcp_get(&mut conn_params) };
+    RawError::convert(ret)?;
+    Ok(conn_params)
+}
+
 pub fn default_security_params() -> raw::ble_gap_sec_params_t {
     let mut sec_params: raw::ble_gap_sec_params_t = unsafe { core::mem::zeroed() };
 
@@ -1,5 +1,7 @@
 //! Generic Attribute client. GATT clients consume functionality offered by GATT servers.
 
+use core::marker::PhantomData;
+
 use heapless::Vec;
 
 use crate::ble::*;
@@ -74,6 +76,36 @@ pub trait Client {
     fn discovery_complete(&mut self) -> Result<(), DiscoverError>;
 }
 
+/// Trait for implementing a set of GATT clients that are discovered together.
+///
+/// This is usually implemented with the `#[gatt_client_set]` macro on a struct whose fields
+/// are all [`Client`]s.
+pub trait ClientSet: Sized {
+    type Event;
+
+    /// Number of services in the set. Must be at most [`CLIENT_SET_MAX`].
+    const SERVICE_COUNT: usize;
+
+    /// Handles notification and indication events from the GATT server.
+    fn on_hvx(&self, conn: &Connection, type_: HvxType, handle: u16, data: &[u8]) -> Option<Self::Event>;
+
+    /// Get the UUID of the service at `index`, which is in `0..SERVICE_COUNT`.
+    fn service_uuid(index: usize) -> Uuid;
+
+    /// Create a new instance in a "not-yet-discovered" state.
+    fn new_undiscovered(conn: Connection) -> Self;
+
+    /// Called by [`discover_set`] for every discovered characteristic of the service at `index`.
+    fn discovered_characteristic(&mut self, index: usize, characteristic: &Characteristic, descriptors: &[Descriptor]);
+
+    /// Called by [`discover_set`] once for every service in the set, after the whole peer database
+    /// has been walked.
+    fn discovery_complete(&mut self, index: usize) -> Result<(), DiscoverError>;
+}
+
+/// Maximum number of services in a [`ClientSet`].
+pub const CLIENT_SET_MAX: usize = 16;
+
 /// Error type for [`discover`]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -146,6 +178,67 @@ pub(crate) async fn discover_service(conn: &Connection, uuid: Uuid) -> Result<ra
 
 // =============================
 
+/// Discover the primary services starting at `start_handle`, keeping only those whose UUID is in `uuids`
+/// and that haven't been `found` yet.
+///
+/// Returns the first instance of each matching service along with its index in `uuids`, marking it
+/// in `found`, and the handle to continue discovery from.
+async fn discover_services(
+    conn: &Connection,
+    start_handle: u16,
+    uuids: &[Uuid],
+    found: &mut [bool],
+) -> Result<(Vec<(usize, raw::ble_gattc_service_t), CLIENT_SET_MAX>, Option<u16>), DiscoverError> {
+    let conn_handle = conn.with_state(|state| state.check_connected())?;
+    let ret = unsafe { raw::sd_ble_gattc_primary_services_discover(conn_handle, start_handle, core::ptr::null()) };
+    RawError::convert(ret).map_err(|err| {
+        warn!("sd_ble_gattc_primary_services_discover err {:?}", err);
+        err
+    })?;
+
+    portal(conn_handle)
+        .wait_once(|ble_evt| unsafe {
+            match (*ble_evt).header.evt_id as u32 {
+                raw::BLE_GAP_EVTS_BLE_GAP_EVT_DISCONNECTED => return Err(DiscoverError::Disconnected),
+                raw::BLE_GATTC_EVTS_BLE_GATTC_EVT_PRIM_SRVC_DISC_RSP => {
+                    let gattc_evt = check_status(ble_evt)?;
+                    let params = get_union_field(ble_evt, &gattc_evt.params.prim_srvc_disc_rsp);
+                    let v = get_flexarray(ble_evt, &params.services, params.count as usize);
+
+                    // Each index is pushed at most once, so this never holds more than `uuids.len()` entries.
+                    let mut res = Vec::new();
+                    for svc in v {
+                        let uuid = Uuid::from_raw(svc.uuid);
+                        let Some(index) = uuids.iter().position(|u| Some(*u) == uuid) else {
+                            continue;
+                        };
+                        if found[index] {
+                            warn!(
+                                "Found more than one service for client {:?}, using the first one",
+                                index
+                            );
+                            continue;
+                        }
+                        if res.push((index, *svc)).is_err() {
+                            return Err(DiscoverError::Raw(RawError::NoMem));
+                        }
+                        found[index] = true;
+                    }
+
+                    let next = match v.last() {
+                        Some(svc) if svc.handle_range.end_handle != u16::MAX => Some(svc.handle_range.end_handle + 1),
+                        _ => None,
+                    };
+                    Ok((res, next))
+                }
+                e => panic!("unexpected event {}", e),
+            }
+        })
+        .await
+}
+
+// =============================
+
 async fn discover_characteristics(
     conn: &Connection,
     start_handle: u16,
@@ -228,9 +321,9 @@ async fn discover_descriptors(
 
 // =============================
 
-async fn discover_inner<T: Client>(
+async fn discover_inner(
     conn: &Connection,
-    client: &mut T,
+    f: &mut impl FnMut(&Characteristic, &[Descriptor]),
     svc: &raw::ble_gattc_service_t,
     curr: raw::ble_gattc_char_t,
     next: Option<raw::ble_gattc_char_t>,
@@ -268,23 +361,17 @@ async fn discover_inner<T: Client>(
         }
     }
 
-    client.discovered_characteristic(&characteristic, &descriptors[..]);
+    f(&characteristic, &descriptors[..]);
 
     Ok(())
 }
 
-/// Discover a service in the peer's GATT server and construct a Client instance
-/// to use it.
-pub async fn discover<T: Client>(conn: &Connection) -> Result<T, DiscoverError> {
-    // TODO handle drop. Probably doable gracefully (no DropBomb)
-
-    let svc = match discover_service(conn, T::uuid()).await {
-        Err(DiscoverError::Gatt(GattError::ATTERR_ATTRIBUTE_NOT_FOUND)) => Err(DiscoverError::ServiceNotFound),
-        x => x,
-    }?;
-
-    let mut client = T::new_undiscovered(conn.clone());
-
+/// Discover all characteristics (and their descriptors) of `svc`.
+async fn discover_characteristics_of(
+    conn: &Connection,
+    svc: &raw::ble_gattc_service_t,
+    mut f: impl FnMut(&Characteristic, &[Descriptor]),
+) -> Result<(), DiscoverError> {
     let mut curr_handle = svc.handle_range.start_handle;
     let end_handle = svc.handle_range.end_handle;
 
@@ -297,21 +384,93 @@ pub async fn discover<T: Client>(conn: &Connection) -> Result<T, DiscoverError>
         assert_ne!(chars.len(), 0);
         for curr in chars {
             if let Some(prev) = prev_char {
-                discover_inner(conn, &mut client, &svc, prev, Some(curr)).await?;
+                discover_inner(conn, &mut f, svc, prev, Some(curr)).await?;
             }
             prev_char = Some(curr);
             curr_handle = curr.handle_value + 1;
         }
     }
     if let Some(prev) = prev_char {
-        discover_inner(conn, &mut client, &svc, prev, None).await?;
+        discover_inner(conn, &mut f, svc, prev, None).await?;
     }
 
+    Ok(())
+}
+
+/// Discover a service in the peer's GATT server and construct a Client instance
+/// to use it.
+pub async fn discover<T: Client>(conn: &Connection) -> Result<T, DiscoverError> {
+    // TODO handle drop. Probably doable gracefully (no DropBomb)
+
+    let svc = match discover_service(conn, T::uuid()).await {
+        Err(DiscoverError::Gatt(GattError::ATTERR_ATTRIBUTE_NOT_FOUND)) => Err(DiscoverError::ServiceNotFound),
+        x => x,
+    }?;
+
+    let mut client = T::new_undiscovered(conn.clone());
+
+    discover_characteristics_of(conn, &svc, |characteristic, descriptors| {
+        client.discovered_characteristic(characteristic, descriptors)
+    })
+    .await?;
+
     client.discovery_complete()?;
 
     Ok(client)
 }
 
+struct CheckServiceCount<T>(PhantomData<T>);
+
+impl<T: ClientSet> CheckServiceCount<T> {
+    // Evaluated at compile time when `discover_set` is instantiated, for sets not defined with `#[gatt_client_set]`.
+    const OK: () = assert!(
+        T::SERVICE_COUNT <= CLIENT_SET_MAX,
+        "ClientSet::SERVICE_COUNT exceeds CLIENT_SET_MAX"
+    );
+}
+
+/// Discover all the services of a [`ClientSet`] in a single pass over the peer's GATT server,
+/// and construct an instance to use them.
+///
+/// If a service is present more than once, the first instance is used.
+pub async fn discover_set<T: ClientSet>(conn: &Connection) -> Result<T, DiscoverError> {
+    #[allow(clippy::let_unit_value)]
+    let () = CheckServiceCount::<T>::OK;
+
+    // Getting the UUIDs up front also registers any vendor-specific bases, so that
+    // the softdevice can report them during discovery.
+    let uuids: Vec<Uuid, CLIENT_SET_MAX> = (0..T::SERVICE_COUNT).map(T::service_uuid).collect();
+
+    let mut set = T::new_undiscovered(conn.clone());
+    let mut found = [false; CLIENT_SET_MAX];
+
+    let mut start_handle = Some(1);
+    while let Some(handle) = start_handle {
+        let (svcs, next) = match discover_services(conn, handle, &uuids, &mut found).await {
+            Err(DiscoverError::Gatt(GattError::ATTERR_ATTRIBUTE_NOT_FOUND)) => break,
+            x => x,
+        }?;
+
+        for (index, svc) in svcs {
+            discover_characteristics_of(conn, &svc, |characteristic, descriptors| {
+                set.discovered_characteristic(index, characteristic, descriptors)
+            })
+            .await?;
+        }
+
+        start_handle = next;
+    }
+
+    for (index, found) in found.iter().enumerate().take(T::SERVICE_COUNT) {
+        if !found {
+            return Err(DiscoverError::ServiceNotFound);
+        }
+        set.discovery_complete(index)?;
+    }
+
+    Ok(set)
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ReadError {
@@ -599,6 +758,7 @@ pub(crate) async fn att_mtu_exchange(conn: &Connection, mtu: u16) -> Result<(),
                     let mtu = params.server_rx_mtu;
                     debug!("att mtu exchange: got mtu {:?}", mtu);
                     conn.with_state(|state| state.att_mtu = mtu);
+                    connection::link_events(conn_handle).call(LinkEvent::AttMtu(mtu));
 
                     Ok(())
                 }
@@ -618,10 +778,37 @@ pub(crate) fn hvx_portal(conn_handle: u16) -> &'static Portal<*const raw::ble_ev
     &HVX_PORTALS[conn_handle as usize]
 }
 
-pub async fn run<'a, F, C>(conn: &Connection, client: &C, mut f: F) -> DisconnectedError
+pub async fn run<'a, F, C>(conn: &Connection, client: &C, f: F) -> DisconnectedError
 where
     F: FnMut(C::Event),
     C: Client,
+{
+    run_inner(
+        conn,
+        |conn, type_, handle, data| client.on_hvx(conn, type_, handle, data),
+        f,
+    )
+    .await
+}
+
+/// Like [`run`], for a [`ClientSet`].
+pub async fn run_set<'a, F, C>(conn: &Connection, set: &C, f: F) -> DisconnectedError
+where
+    F: FnMut(C::Event),
+    C: ClientSet,
+{
+    run_inner(
+        conn,
+        |conn, type_, handle, data| set.on_hvx(conn, type_, handle, data),
+        f,
+    )
+    .await
+}
+
+async fn run_inner<E, H, F>(conn: &Connection, mut on_hvx: H, mut f: F) -> DisconnectedError
+where
+    H: FnMut(&Connection, HvxType, u16, &[u8]) -> Option<E>,
+    F: FnMut(E),
 {
     let handle = match conn.with_state(|state| state.check_connected()) {
         Ok(handle) => handle,
@@ -650,7 +837,7 @@ where
                     );
 
                     match params.type_.try_into() {
-                        Ok(type_) => client.on_hvx(&conn, type_, params.handle, v),
+                        Ok(type_) => on_hvx(&conn, type_, params.handle, v),
                         Err(_) => {
                             error!("gatt_client invalid hvx type: {}", params.type_);
                             None
@@ -11,6 +11,7 @@ use crate::{raw, RawError, Softdevice};
 
 pub mod builder;
 pub mod characteristic;
+pub mod table;
 
 pub struct Characteristic {
     pub uuid: Uuid,
@@ -32,11 +33,20 @@ pub struct CharacteristicHandles {
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct ServiceHandle(u16);
+pub struct ServiceHandle {
+    handle: u16,
+    end_handle: u16,
+}
 
 impl ServiceHandle {
+    /// Handle of the service declaration, which is also the first handle of the service.
     pub fn handle(&self) -> u16 {
-        self.0
+        self.handle
+    }
+
+    /// Last attribute handle belonging to the service.
+    pub fn end_handle(&self) -> u16 {
+        self.end_handle
     }
 }
 
@@ -91,6 +101,115 @@ impl TryFrom<u8> for WriteOp {
     }
 }
 
+/// Attribute table bytes assumed per attribute, on top of its value, when estimating the table size.
+///
+/// This is a heuristic: the softdevice doesn't document the layout of its attribute table, so this
+/// errs on the large side. If the table turns out too small, registering the services fails with
+/// `RegisterError::Raw(RawError::NoMem)`.
+pub const ATTR_TAB_ENTRY_OVERHEAD: usize = 16;
+
+/// Attributes of the GAP and GATT services added by the softdevice: the GAP service with device name,
+/// appearance, PPCP and central address resolution, and the GATT service with service changed.
+const BUILTIN_ATTR_COUNT: usize = 13;
+/// Values of the builtin attributes, rounded up, with the default device name length. A longer
+/// device name takes more space.
+const BUILTIN_VALUE_SIZE: usize = 64 + raw::BLE_GAP_DEVNAME_DEFAULT_LEN as usize;
+
+/// Estimate the attribute table size for `attr_count` attributes holding `value_size` bytes of values
+/// in total, including the GAP and GATT services added by the softdevice.
+///
+/// This is a heuristic, see [`ATTR_TAB_ENTRY_OVERHEAD`]. The result is at least
+/// `BLE_GATTS_ATTR_TAB_SIZE_MIN`, the smallest size the softdevice accepts.
+///
+/// This is what `#[gatt_server]` uses to compute `ATTR_TAB_SIZE`.
+pub const fn attr_tab_size(attr_count: usize, value_size: usize) -> u32 {
+    let size = (BUILTIN_ATTR_COUNT + attr_count) * ATTR_TAB_ENTRY_OVERHEAD + BUILTIN_VALUE_SIZE + value_size;
+    // The softdevice requires a multiple of 4.
+    let size = ((size + 3) & !3) as u32;
+    if size < raw::BLE_GATTS_ATTR_TAB_SIZE_MIN {
+        raw::BLE_GATTS_ATTR_TAB_SIZE_MIN
+    } else {
+        size
+    }
+}
+
+/// Count the distinct vendor-specific UUID bases used by a set of 128-bit UUIDs.
+///
+/// UUIDs are in little-endian format, as passed to [`Uuid::new_128`]. Octets 12-13 are not part
+/// of the base.
+///
+/// This is what `#[gatt_server]` uses to compute `VS_UUID_COUNT`.
+pub const fn vs_uuid_count(uuids: &[&[[u8; 16]]]) -> u8 {
+    const fn same_base(a: &[u8; 16], b: &[u8; 16]) -> bool {
+        let mut i = 0;
+        while i < 16 {
+            if i != 12 && i != 13 && a[i] != b[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    let mut count = 0;
+    let mut i = 0;
+    while i < uuids.len() {
+        let mut j = 0;
+        while j < uuids[i].len() {
+            // Only count the first occurrence of each base.
+            let mut seen = false;
+            let mut k = 0;
+            while k <= i && !seen {
+                let mut l = 0;
+                let end = if k == i { j } else { uuids[k].len() };
+                while l < end && !seen {
+                    seen = same_base(&uuids[i][j], &uuids[k][l]);
+                    l += 1;
+                }
+                k += 1;
+            }
+            if !seen {
+                count += 1;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Total length of `parts`, used by `#[gatt_server]` to size its `GATT_SCHEMA`.
+#[doc(hidden)]
+pub const fn schema_len(parts: &[&str]) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < parts.len() {
+        len += parts[i].len();
+        i += 1;
+    }
+    len
+}
+
+/// Concatenate `parts` at compile time, used by `#[gatt_server]` to inline the services' `GATT_SCHEMA`
+/// into its own.
+#[doc(hidden)]
+pub const fn schema_concat<const N: usize>(parts: &[&str]) -> [u8; N] {
+    let mut res = [0; N];
+    let mut n = 0;
+    let mut i = 0;
+    while i < parts.len() {
+        let bytes = parts[i].as_bytes();
+        let mut j = 0;
+        while j < bytes.len() {
+            res[n] = bytes[j];
+            n += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+    res
+}
+
 pub trait Server: Sized {
     type Event;
 
@@ -148,6 +267,21 @@ pub trait Server: Sized {
 pub trait Service: Sized {
     type Event;
 
+    /// Number of attributes in the service, for estimating the attribute table size.
+    ///
+    /// Set by `#[gatt_service]`. Hand-written services that don't override this aren't accounted for
+    /// in the `ATTR_TAB_SIZE` of a `#[gatt_server]`.
+    const ATTR_COUNT: usize = 0;
+
+    /// Total size of the attribute values in the service, for estimating the attribute table size.
+    const ATTR_VALUE_SIZE: usize = 0;
+
+    /// 128-bit UUIDs used by the service, for counting the vendor-specific UUID bases.
+    const VS_UUID_BASES: &'static [[u8; 16]] = &[];
+
+    /// Layout of the service as JSON, `null` if unknown.
+    const GATT_SCHEMA: &'static str = "null";
+
     fn on_write(&self, handle: u16, data: &[u8]) -> Option<Self::Event>;
 }
 
@@ -159,6 +293,9 @@ pub enum RegisterError {
 
 impl From<RawError> for RegisterError {
     fn from(err: RawError) -> Self {
+        if err == RawError::NoMem {
+            warn!("attribute table full, increase Config::gatts_attr_tab_size");
+        }
         RegisterError::Raw(err)
     }
 }
@@ -461,6 +598,7 @@ pub(crate) unsafe fn on_evt(ble_evt: *const raw::ble_evt_t) {
             connection::with_state_by_conn_handle(conn_handle, |state| {
                 state.att_mtu = mtu;
             });
+            connection::link_events(conn_handle).call(LinkEvent::AttMtu(mtu));
         }
         _ => {
             portal(gatts_evt.conn_handle).call(ble_evt);
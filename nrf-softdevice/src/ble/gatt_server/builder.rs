@@ -2,7 +2,7 @@
 
 use core::marker::PhantomData;
 use core::mem;
-use core::ptr::null;
+use core::ptr::{null, null_mut};
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
@@ -17,28 +17,37 @@ use crate::{raw, RawError, Softdevice};
 
 pub struct ServiceBuilder<'a> {
     handle: u16,
+    end_handle: u16,
     sd: PhantomData<&'a mut Softdevice>,
 }
 
 pub struct CharacteristicBuilder<'a> {
     handles: CharacteristicHandles,
-    sb: PhantomData<&'a ServiceBuilder<'a>>,
+    end_handle: &'a mut u16,
 }
 
 impl<'a> ServiceBuilder<'a> {
-    pub fn new(_sd: &'a mut Softdevice, uuid: Uuid) -> Result<Self, RegisterError> {
+    /// Start building a primary service.
+    pub fn new(sd: &'a mut Softdevice, uuid: Uuid) -> Result<Self, RegisterError> {
+        Self::new_inner(sd, uuid, raw::BLE_GATTS_SRVC_TYPE_PRIMARY)
+    }
+
+    /// Start building a secondary service.
+    ///
+    /// Secondary services are only meant to be referenced from other services with
+    /// [`include_service`][Self::include_service].
+    pub fn new_secondary(sd: &'a mut Softdevice, uuid: Uuid) -> Result<Self, RegisterError> {
+        Self::new_inner(sd, uuid, raw::BLE_GATTS_SRVC_TYPE_SECONDARY)
+    }
+
+    fn new_inner(_sd: &'a mut Softdevice, uuid: Uuid, type_: u32) -> Result<Self, RegisterError> {
         let mut service_handle: u16 = 0;
-        let ret = unsafe {
-            raw::sd_ble_gatts_service_add(
-                raw::BLE_GATTS_SRVC_TYPE_PRIMARY as u8,
-                uuid.as_raw_ptr(),
-                &mut service_handle as _,
-            )
-        };
+        let ret = unsafe { raw::sd_ble_gatts_service_add(type_ as u8, uuid.as_raw_ptr(), &mut service_handle as _) };
         RawError::convert(ret)?;
 
         Ok(ServiceBuilder {
             handle: service_handle,
+            end_handle: service_handle,
             sd: PhantomData,
         })
     }
@@ -122,22 +131,39 @@ impl<'a> ServiceBuilder<'a> {
             sccd_handle: handles.sccd_handle,
         };
 
+        // Attribute handles are allocated sequentially. The handle of the presentation format
+        // descriptor isn't returned, so find the last attribute by probing past the highest known one.
+        let mut end_handle = handles
+            .value_handle
+            .max(handles.user_desc_handle)
+            .max(handles.cccd_handle)
+            .max(handles.sccd_handle);
+        while let Some(next) = end_handle.checked_add(1).filter(|&h| attr_exists(h)) {
+            end_handle = next;
+        }
+        self.end_handle = end_handle;
+
         Ok(CharacteristicBuilder {
             handles,
-            sb: PhantomData,
+            end_handle: &mut self.end_handle,
         })
     }
 
     pub fn include_service(&mut self, service: &ServiceHandle) -> Result<IncludedServiceHandle, RegisterError> {
         let mut handle = 0;
-        let ret = unsafe { raw::sd_ble_gatts_include_add(self.handle, service.0, &mut handle as _) };
+        let ret = unsafe { raw::sd_ble_gatts_include_add(self.handle, service.handle(), &mut handle as _) };
         RawError::convert(ret)?;
 
+        self.end_handle = handle;
+
         Ok(IncludedServiceHandle(handle))
     }
 
     pub fn build(self) -> ServiceHandle {
-        ServiceHandle(self.handle)
+        ServiceHandle {
+            handle: self.handle,
+            end_handle: self.end_handle,
+        }
     }
 }
 
@@ -183,6 +209,8 @@ impl<'a> CharacteristicBuilder<'a> {
         let ret = unsafe { raw::sd_ble_gatts_descriptor_add(self.handles.value_handle, &attr as _, &mut handle as _) };
         RawError::convert(ret)?;
 
+        *self.end_handle = handle;
+
         Ok(DescriptorHandle(handle))
     }
 
@@ -190,3 +218,9 @@ impl<'a> CharacteristicBuilder<'a> {
         self.handles
     }
 }
+
+fn attr_exists(handle: u16) -> bool {
+    let mut uuid: raw::ble_uuid_t = unsafe { mem::zeroed() };
+    let ret = unsafe { raw::sd_ble_gatts_attr_get(handle, &mut uuid, null_mut()) };
+    RawError::convert(ret).is_ok()
+}
@@ -0,0 +1,100 @@
+//! Introspection of the attribute table registered in the softdevice.
+
+use core::ptr::null_mut;
+
+use super::characteristic::AttributeMetadata;
+use crate::ble::{SecurityMode, Uuid};
+use crate::{raw, RawError, Softdevice};
+
+/// An attribute registered in the softdevice attribute table.
+#[derive(Clone, Copy)]
+pub struct AttributeInfo {
+    pub handle: u16,
+    /// `None` if the attribute has a vendor-specific UUID whose base was not registered.
+    pub uuid: Option<Uuid>,
+    pub metadata: AttributeMetadata,
+    /// Current length of the value, `None` if the softdevice doesn't allow reading it.
+    pub len: Option<usize>,
+}
+
+/// Get the first attribute handle available to the application.
+///
+/// Handles below this one belong to the GAP and GATT services added by the softdevice.
+pub fn initial_user_handle(_sd: &Softdevice) -> Result<u16, RawError> {
+    let mut handle = 0;
+    let ret = unsafe { raw::sd_ble_gatts_initial_user_handle_get(&mut handle) };
+    RawError::convert(ret)?;
+    Ok(handle)
+}
+
+/// Get the attribute at `handle`, or `None` if there is no such attribute.
+pub fn attribute(_sd: &Softdevice, handle: u16) -> Result<Option<AttributeInfo>, RawError> {
+    let mut uuid: raw::ble_uuid_t = unsafe { core::mem::zeroed() };
+    let mut md: raw::ble_gatts_attr_md_t = unsafe { core::mem::zeroed() };
+    let ret = unsafe { raw::sd_ble_gatts_attr_get(handle, &mut uuid, &mut md) };
+    match RawError::convert(ret) {
+        Ok(()) => {}
+        Err(RawError::NotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let mut value = raw::ble_gatts_value_t {
+        p_value: null_mut(),
+        len: 0,
+        offset: 0,
+    };
+    let ret = unsafe { raw::sd_ble_gatts_value_get(raw::BLE_CONN_HANDLE_INVALID as u16, handle, &mut value) };
+    let len = RawError::convert(ret).ok().map(|_| usize::from(value.len));
+
+    Ok(Some(AttributeInfo {
+        handle,
+        uuid: Uuid::from_raw(uuid),
+        metadata: AttributeMetadata {
+            read: SecurityMode::try_from_raw(md.read_perm).unwrap_or(SecurityMode::NoAccess),
+            write: SecurityMode::try_from_raw(md.write_perm).unwrap_or(SecurityMode::NoAccess),
+            variable_len: md.vlen() != 0,
+            deferred_read: md.rd_auth() != 0,
+            deferred_write: md.wr_auth() != 0,
+        },
+        len,
+    }))
+}
+
+/// Iterate over all the attributes in the table, starting at `start_handle`.
+///
+/// Use `1` to include the attributes added by the softdevice, or [`initial_user_handle`]
+/// to only get the ones added by the application.
+pub fn attributes(sd: &Softdevice, start_handle: u16) -> Attributes<'_> {
+    Attributes {
+        sd,
+        handle: Some(start_handle),
+    }
+}
+
+/// Iterator over the attribute table, see [`attributes`].
+pub struct Attributes<'a> {
+    sd: &'a Softdevice,
+    handle: Option<u16>,
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Result<AttributeInfo, RawError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let handle = self.handle?;
+        match attribute(self.sd, handle) {
+            Ok(Some(attr)) => {
+                self.handle = handle.checked_add(1);
+                Some(Ok(attr))
+            }
+            Ok(None) => {
+                self.handle = None;
+                None
+            }
+            Err(e) => {
+                self.handle = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use core::{mem, slice};
 
+use fixed::{FixedI16, FixedI32, FixedI64, FixedI8, FixedU16, FixedU32, FixedU64, FixedU8};
 use heapless::{String, Vec};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -58,6 +59,16 @@ unsafe impl Primitive for i32 {}
 unsafe impl Primitive for i64 {}
 unsafe impl Primitive for f32 {}
 unsafe impl Primitive for f64 {}
+unsafe impl<Frac> Primitive for FixedU8<Frac> {}
+unsafe impl<Frac> Primitive for FixedU16<Frac> {}
+unsafe impl<Frac> Primitive for FixedU32<Frac> {}
+unsafe impl<Frac> Primitive for FixedU64<Frac> {}
+unsafe impl<Frac> Primitive for FixedI8<Frac> {}
+unsafe impl<Frac> Primitive for FixedI16<Frac> {}
+unsafe impl<Frac> Primitive for FixedI32<Frac> {}
+unsafe impl<Frac> Primitive for FixedI64<Frac> {}
+unsafe impl Primitive for SFloat {}
+unsafe impl Primitive for Float {}
 
 impl<T: Primitive> FixedGattValue for T {
     const SIZE: usize = mem::size_of::<Self>();
@@ -136,3 +147,433 @@ impl<const N: usize> GattValue for String<N> {
         self.as_ref()
     }
 }
+
+/// Unsigned 24-bit integer (`uint24`).
+#[repr(transparent)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct U24([u8; 3]);
+
+impl U24 {
+    pub const MAX: u32 = 0x00FF_FFFF;
+
+    /// Returns `None` if `val` does not fit in 24 bits.
+    pub const fn new(val: u32) -> Option<Self> {
+        if val > Self::MAX {
+            return None;
+        }
+        let b = val.to_le_bytes();
+        Some(Self([b[0], b[1], b[2]]))
+    }
+
+    pub const fn get(self) -> u32 {
+        u32::from_le_bytes([self.0[0], self.0[1], self.0[2], 0])
+    }
+}
+
+impl From<U24> for u32 {
+    fn from(val: U24) -> Self {
+        val.get()
+    }
+}
+
+impl FixedGattValue for U24 {
+    const SIZE: usize = 3;
+
+    fn from_gatt(data: &[u8]) -> Self {
+        Self(unwrap!(data.try_into()))
+    }
+
+    fn to_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Unsigned 48-bit integer (`uint48`).
+#[repr(transparent)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct U48([u8; 6]);
+
+impl U48 {
+    pub const MAX: u64 = 0x0000_FFFF_FFFF_FFFF;
+
+    /// Returns `None` if `val` does not fit in 48 bits.
+    pub const fn new(val: u64) -> Option<Self> {
+        if val > Self::MAX {
+            return None;
+        }
+        let b = val.to_le_bytes();
+        Some(Self([b[0], b[1], b[2], b[3], b[4], b[5]]))
+    }
+
+    pub const fn get(self) -> u64 {
+        u64::from_le_bytes([self.0[0], self.0[1], self.0[2], self.0[3], self.0[4], self.0[5], 0, 0])
+    }
+}
+
+impl From<U48> for u64 {
+    fn from(val: U48) -> Self {
+        val.get()
+    }
+}
+
+impl FixedGattValue for U48 {
+    const SIZE: usize = 6;
+
+    fn from_gatt(data: &[u8]) -> Self {
+        Self(unwrap!(data.try_into()))
+    }
+
+    fn to_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Powers of ten representable as `f32`, written out so each one is correctly rounded.
+const POW10: [f32; 39] = [
+    1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20,
+    1e21, 1e22, 1e23, 1e24, 1e25, 1e26, 1e27, 1e28, 1e29, 1e30, 1e31, 1e32, 1e33, 1e34, 1e35, 1e36, 1e37, 1e38,
+];
+
+/// `val * 10^exponent`, rounded once for exponents within the `f32` range.
+fn scale10(val: f32, exponent: i32) -> f32 {
+    let e = exponent.unsigned_abs() as usize;
+    let max = POW10.len() - 1;
+    // Beyond the table, the result saturates to infinity or zero anyway.
+    let (hi, lo) = if e <= max {
+        (POW10[e], 1.0)
+    } else {
+        (POW10[max], POW10[(e - max).min(max)])
+    };
+    if exponent >= 0 {
+        val * hi * lo
+    } else {
+        val / hi / lo
+    }
+}
+
+fn round(val: f32) -> f32 {
+    if val < 0.0 {
+        (val - 0.5) as i32 as f32
+    } else {
+        (val + 0.5) as i32 as f32
+    }
+}
+
+/// IEEE-11073 16-bit short floating point value (`SFLOAT`).
+///
+/// The value is `mantissa * 10^exponent`, with a 12-bit signed mantissa and a 4-bit signed exponent.
+/// This is the encoding used by the Blood Pressure, Glucose and Weight Scale profiles.
+#[repr(transparent)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SFloat(u16);
+
+impl SFloat {
+    pub const NAN: Self = Self(0x07FF);
+    pub const NRES: Self = Self(0x0800);
+    pub const INFINITY: Self = Self(0x07FE);
+    pub const NEG_INFINITY: Self = Self(0x0802);
+    pub const RESERVED: Self = Self(0x0801);
+
+    const MANTISSA_MAX: i16 = 0x07FD;
+    const MANTISSA_MIN: i16 = -0x07FD;
+    const EXPONENT_MAX: i8 = 7;
+    const EXPONENT_MIN: i8 = -8;
+
+    /// Build a value from its mantissa and exponent.
+    ///
+    /// Returns `None` if either doesn't fit, or if the mantissa collides with a special value.
+    pub const fn new(mantissa: i16, exponent: i8) -> Option<Self> {
+        if mantissa < Self::MANTISSA_MIN
+            || mantissa > Self::MANTISSA_MAX
+            || exponent < Self::EXPONENT_MIN
+            || exponent > Self::EXPONENT_MAX
+        {
+            return None;
+        }
+        Some(Self(
+            (((exponent as u16) & 0x000F) << 12) | ((mantissa as u16) & 0x0FFF),
+        ))
+    }
+
+    /// Convert `val` to the closest value with the given decimal `exponent`.
+    ///
+    /// Out of range values saturate to [`SFloat::INFINITY`] or [`SFloat::NEG_INFINITY`].
+    pub fn from_f32(val: f32, exponent: i8) -> Self {
+        if val.is_nan() {
+            return Self::NAN;
+        }
+        let exponent = exponent.clamp(Self::EXPONENT_MIN, Self::EXPONENT_MAX);
+        let mantissa = round(scale10(val, -i32::from(exponent)));
+        if mantissa > Self::MANTISSA_MAX as f32 {
+            Self::INFINITY
+        } else if mantissa < Self::MANTISSA_MIN as f32 {
+            Self::NEG_INFINITY
+        } else {
+            unwrap!(Self::new(mantissa as i16, exponent))
+        }
+    }
+
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> u16 {
+        self.0
+    }
+
+    pub const fn mantissa(self) -> i16 {
+        // Sign-extend the low 12 bits.
+        ((self.0 << 4) as i16) >> 4
+    }
+
+    pub const fn exponent(self) -> i8 {
+        (self.0 as i16 >> 12) as i8
+    }
+
+    /// Returns `true` for NaN, NRes, the infinities and the reserved value.
+    pub const fn is_special(self) -> bool {
+        self.exponent() == 0 && matches!(self.mantissa(), 0x07FE | 0x07FF | -0x0800 | -0x07FF | -0x07FE)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        match self {
+            Self::INFINITY => f32::INFINITY,
+            Self::NEG_INFINITY => f32::NEG_INFINITY,
+            _ if self.is_special() => f32::NAN,
+            _ => scale10(self.mantissa() as f32, i32::from(self.exponent())),
+        }
+    }
+}
+
+impl From<SFloat> for f32 {
+    fn from(val: SFloat) -> Self {
+        val.to_f32()
+    }
+}
+
+/// IEEE-11073 32-bit floating point value (`FLOAT`).
+///
+/// The value is `mantissa * 10^exponent`, with a 24-bit signed mantissa and an 8-bit signed exponent.
+/// This is the encoding used by the Health Thermometer and Pulse Oximeter profiles.
+#[repr(transparent)]
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Float(u32);
+
+impl Float {
+    pub const NAN: Self = Self(0x007F_FFFF);
+    pub const NRES: Self = Self(0x0080_0000);
+    pub const INFINITY: Self = Self(0x007F_FFFE);
+    pub const NEG_INFINITY: Self = Self(0x0080_0002);
+    pub const RESERVED: Self = Self(0x0080_0001);
+
+    const MANTISSA_MAX: i32 = 0x007F_FFFD;
+    const MANTISSA_MIN: i32 = -0x007F_FFFD;
+
+    /// Build a value from its mantissa and exponent.
+    ///
+    /// Returns `None` if the mantissa doesn't fit, or if it collides with a special value.
+    pub const fn new(mantissa: i32, exponent: i8) -> Option<Self> {
+        if mantissa < Self::MANTISSA_MIN || mantissa > Self::MANTISSA_MAX {
+            return None;
+        }
+        Some(Self(
+            ((exponent as u8 as u32) << 24) | ((mantissa as u32) & 0x00FF_FFFF),
+        ))
+    }
+
+    /// Convert `val` to the closest value with the given decimal `exponent`.
+    ///
+    /// Out of range values saturate to [`Float::INFINITY`] or [`Float::NEG_INFINITY`].
+    pub fn from_f32(val: f32, exponent: i8) -> Self {
+        if val.is_nan() {
+            return Self::NAN;
+        }
+        let mantissa = round(scale10(val, -i32::from(exponent)));
+        if mantissa > Self::MANTISSA_MAX as f32 {
+            Self::INFINITY
+        } else if mantissa < Self::MANTISSA_MIN as f32 {
+            Self::NEG_INFINITY
+        } else {
+            unwrap!(Self::new(mantissa as i32, exponent))
+        }
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn mantissa(self) -> i32 {
+        // Sign-extend the low 24 bits.
+        ((self.0 << 8) as i32) >> 8
+    }
+
+    pub const fn exponent(self) -> i8 {
+        (self.0 >> 24) as u8 as i8
+    }
+
+    /// Returns `true` for NaN, NRes, the infinities and the reserved value.
+    pub const fn is_special(self) -> bool {
+        self.exponent() == 0
+            && matches!(
+                self.mantissa(),
+                0x007F_FFFE | 0x007F_FFFF | -0x0080_0000 | -0x007F_FFFF | -0x007F_FFFE
+            )
+    }
+
+    pub fn to_f32(self) -> f32 {
+        match self {
+            Self::INFINITY => f32::INFINITY,
+            Self::NEG_INFINITY => f32::NEG_INFINITY,
+            _ if self.is_special() => f32::NAN,
+            _ => scale10(self.mantissa() as f32, i32::from(self.exponent())),
+        }
+    }
+}
+
+impl From<Float> for f32 {
+    fn from(val: Float) -> Self {
+        val.to_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn u24_layout() {
+        let v = unwrap!(U24::new(0x12_3456));
+        assert_eq!(FixedGattValue::to_gatt(&v), &[0x56, 0x34, 0x12]);
+        assert_eq!(<U24 as FixedGattValue>::from_gatt(&[0x56, 0x34, 0x12]).get(), 0x12_3456);
+        assert_eq!(U24::new(U24::MAX).map(U24::get), Some(U24::MAX));
+        assert_eq!(U24::new(U24::MAX + 1), None);
+    }
+
+    #[test]
+    fn u48_layout() {
+        let v = unwrap!(U48::new(0x1234_5678_9ABC));
+        assert_eq!(FixedGattValue::to_gatt(&v), &[0xBC, 0x9A, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(
+            <U48 as FixedGattValue>::from_gatt(&[0xBC, 0x9A, 0x78, 0x56, 0x34, 0x12]).get(),
+            0x1234_5678_9ABC
+        );
+        assert_eq!(U48::new(U48::MAX).map(U48::get), Some(U48::MAX));
+        assert_eq!(U48::new(U48::MAX + 1), None);
+    }
+
+    #[test]
+    fn sfloat_layout() {
+        let v = unwrap!(SFloat::new(114, -1));
+        assert_eq!(v.to_bits(), 0xF072);
+        assert_eq!(v.mantissa(), 114);
+        assert_eq!(v.exponent(), -1);
+        assert_eq!(v.to_f32(), 11.4);
+
+        let v = SFloat::from_bits(0x2F9C);
+        assert_eq!(v.mantissa(), -100);
+        assert_eq!(v.exponent(), 2);
+        assert_eq!(v.to_f32(), -10000.0);
+
+        assert_eq!(SFloat::new(0x07FE, 0), None);
+        assert_eq!(SFloat::new(-0x07FE, 0), None);
+        assert_eq!(SFloat::new(0, 8), None);
+        assert_eq!(SFloat::new(0, -9), None);
+    }
+
+    #[test]
+    fn sfloat_special() {
+        assert!(SFloat::NAN.to_f32().is_nan());
+        assert!(SFloat::NRES.to_f32().is_nan());
+        assert!(SFloat::RESERVED.to_f32().is_nan());
+        assert_eq!(SFloat::INFINITY.to_f32(), f32::INFINITY);
+        assert_eq!(SFloat::NEG_INFINITY.to_f32(), f32::NEG_INFINITY);
+        assert!(!unwrap!(SFloat::new(0x07FD, 0)).is_special());
+        assert!(!unwrap!(SFloat::new(-0x07FD, 0)).is_special());
+
+        assert_eq!(SFloat::from_f32(f32::NAN, 0), SFloat::NAN);
+        assert_eq!(SFloat::from_f32(f32::INFINITY, 0), SFloat::INFINITY);
+        assert_eq!(SFloat::from_f32(2046.0, 0), SFloat::INFINITY);
+        assert_eq!(SFloat::from_f32(-2046.0, 0), SFloat::NEG_INFINITY);
+    }
+
+    #[test]
+    fn sfloat_rounding() {
+        assert_eq!(SFloat::from_f32(0.25, -1), unwrap!(SFloat::new(3, -1)));
+        assert_eq!(SFloat::from_f32(-0.25, -1), unwrap!(SFloat::new(-3, -1)));
+        assert_eq!(SFloat::from_f32(0.24, -1), unwrap!(SFloat::new(2, -1)));
+        assert_eq!(SFloat::from_f32(1234.0, 1), unwrap!(SFloat::new(123, 1)));
+        assert_eq!(SFloat::from_f32(11.4, -1), unwrap!(SFloat::new(114, -1)));
+    }
+
+    #[test]
+    fn sfloat_round_trip() {
+        for exponent in SFloat::EXPONENT_MIN..=SFloat::EXPONENT_MAX {
+            for mantissa in SFloat::MANTISSA_MIN..=SFloat::MANTISSA_MAX {
+                let v = unwrap!(SFloat::new(mantissa, exponent));
+                assert_eq!(SFloat::from_bits(v.to_bits()), v);
+                assert_eq!(SFloat::from_f32(v.to_f32(), exponent), v);
+            }
+        }
+    }
+
+    #[test]
+    fn float_layout() {
+        let v = unwrap!(Float::new(3655, -2));
+        assert_eq!(v.to_bits(), 0xFE00_0E47);
+        assert_eq!(v.mantissa(), 3655);
+        assert_eq!(v.exponent(), -2);
+        assert_eq!(v.to_f32(), 36.55);
+
+        let v = Float::from_bits(0x03FF_FFFF);
+        assert_eq!(v.mantissa(), -1);
+        assert_eq!(v.exponent(), 3);
+        assert_eq!(v.to_f32(), -1000.0);
+
+        assert_eq!(Float::new(0x7F_FFFE, 0), None);
+        assert_eq!(Float::new(-0x7F_FFFE, 0), None);
+    }
+
+    #[test]
+    fn float_special() {
+        assert!(Float::NAN.to_f32().is_nan());
+        assert!(Float::NRES.to_f32().is_nan());
+        assert!(Float::RESERVED.to_f32().is_nan());
+        assert_eq!(Float::INFINITY.to_f32(), f32::INFINITY);
+        assert_eq!(Float::NEG_INFINITY.to_f32(), f32::NEG_INFINITY);
+        assert!(!unwrap!(Float::new(0x7F_FFFD, 0)).is_special());
+
+        assert_eq!(Float::from_f32(f32::NAN, 0), Float::NAN);
+        assert_eq!(Float::from_f32(1e10, 0), Float::INFINITY);
+        assert_eq!(Float::from_f32(-1e10, 0), Float::NEG_INFINITY);
+        assert_eq!(Float::from_f32(1e-10, 0), unwrap!(Float::new(0, 0)));
+    }
+
+    #[test]
+    fn float_rounding() {
+        assert_eq!(Float::from_f32(36.55, -2), unwrap!(Float::new(3655, -2)));
+        assert_eq!(Float::from_f32(0.125, -2), unwrap!(Float::new(13, -2)));
+        assert_eq!(Float::from_f32(-0.125, -2), unwrap!(Float::new(-13, -2)));
+        assert_eq!(Float::from_f32(1.5e30, 29), unwrap!(Float::new(15, 29)));
+        assert_eq!(Float::from_f32(1.5e-30, -31), unwrap!(Float::new(15, -31)));
+    }
+
+    #[test]
+    fn float_round_trip() {
+        // Large mantissas lose a bit to the two `f32` roundings, so stay within 20 bits.
+        for exponent in -30..=30 {
+            for mantissa in (-0x0F_FFFF..=0x0F_FFFF).step_by(0x1001) {
+                let v = unwrap!(Float::new(mantissa, exponent));
+                assert_eq!(Float::from_bits(v.to_bits()), v);
+                assert_eq!(Float::from_f32(v.to_f32(), exponent), v);
+            }
+        }
+    }
+}
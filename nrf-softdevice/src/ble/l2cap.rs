@@ -181,7 +181,7 @@ static mut PACKET_FREE: Option<unsafe fn(NonNull<u8>)> = None;
 
 impl<P: Packet> L2cap<P> {
     /// Initialize the driver.
-    /// Panics if called multiple times.
+    /// Panics if called while another instance is alive.
     pub fn init(_sd: &Softdevice) -> Self {
         if IS_INIT
             .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
@@ -366,6 +366,16 @@ impl<P: Packet> L2cap<P> {
     }
 }
 
+impl<P: Packet> Drop for L2cap<P> {
+    fn drop(&mut self) {
+        IS_INIT.store(false, Ordering::Release);
+    }
+}
+
+pub(crate) fn is_init() -> bool {
+    IS_INIT.load(Ordering::Acquire)
+}
+
 /// Configuration for an L2CAP channel.
 pub struct Config {
     /// Number of credits that the SoftDevice will make sure the peer
@@ -1,8 +1,11 @@
 //! Bluetooth Low Energy
 
+pub mod conn_params;
 mod connection;
 mod gap;
 mod gatt_traits;
+pub mod options;
+pub mod phy;
 mod replies;
 mod types;
 
@@ -33,6 +36,12 @@ pub mod gatt_server;
 #[cfg(feature = "ble-l2cap")]
 pub mod l2cap;
 
+#[cfg(all(feature = "ble-rssi", not(feature = "s122")))]
+pub mod tx_power;
+
+#[cfg(any(feature = "s132", feature = "s140"))]
+pub mod channel_survey;
+
 use core::mem;
 
 #[cfg(any(feature = "ble-gatt-server", feature = "ble-sec"))]
@@ -0,0 +1,203 @@
+//! Typed access to the BLE options set with `sd_ble_opt_set` and read with `sd_ble_opt_get`.
+//!
+//! Options can be changed at any time while the softdevice is enabled. Options applying to a single
+//! connection take a [`Connection`], the others apply to the whole stack.
+//!
+//! The softdevice only supports reading back the channel map and the authenticated payload timeout,
+//! so the other options only have setters.
+
+use crate::ble::{Connection, DisconnectedError};
+use crate::{raw, RawError, Softdevice};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OptionError {
+    Disconnected,
+    Raw(RawError),
+}
+
+impl From<DisconnectedError> for OptionError {
+    fn from(_err: DisconnectedError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl From<RawError> for OptionError {
+    fn from(err: RawError) -> Self {
+        Self::Raw(err)
+    }
+}
+
+fn set_opt(opt_id: u32, opt: raw::ble_opt_t) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_ble_opt_set(opt_id, &opt) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_ble_opt_set {:?} err {:?}", opt_id, err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn get_opt(opt_id: u32, mut opt: raw::ble_opt_t) -> Result<raw::ble_opt_t, RawError> {
+    let ret = unsafe { raw::sd_ble_opt_get(opt_id, &mut opt) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_ble_opt_get {:?} err {:?}", opt_id, err);
+        return Err(err);
+    }
+    Ok(opt)
+}
+
+/// Set the data channel map used by central connections.
+///
+/// `ch_map` is a 37-bit bitmap of the data channels, channel 0 being the LSB of `ch_map[0]`. At least
+/// two channels must be enabled, and the advertising channels (bits 37 to 39) must be cleared.
+///
+/// By the Bluetooth specification, the channel map can't be set again until at least 1 s has passed,
+/// [`RawError::Busy`] is returned if it is.
+#[cfg(feature = "ble-central")]
+pub fn set_channel_map(_sd: &Softdevice, ch_map: [u8; 5]) -> Result<(), RawError> {
+    set_opt(
+        raw::BLE_GAP_OPTS_BLE_GAP_OPT_CH_MAP,
+        raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t {
+                ch_map: raw::ble_gap_opt_ch_map_t { conn_handle: 0, ch_map },
+            },
+        },
+    )
+}
+
+/// Get the data channel map currently used by the connection.
+pub fn get_channel_map(conn: &Connection) -> Result<[u8; 5], OptionError> {
+    let conn_handle = conn.with_state(|state| state.check_connected())?;
+    let opt = get_opt(
+        raw::BLE_GAP_OPTS_BLE_GAP_OPT_CH_MAP,
+        raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t {
+                ch_map: raw::ble_gap_opt_ch_map_t {
+                    conn_handle,
+                    ch_map: [0; 5],
+                },
+            },
+        },
+    )?;
+    Ok(unsafe { opt.gap_opt.ch_map.ch_map })
+}
+
+/// Set a static passkey to use during pairing instead of a random one, or `None` to go back to random passkeys.
+///
+/// The passkey must be 6 ASCII digits, otherwise [`RawError::InvalidParam`] is returned.
+///
+/// Reusing the same passkey makes pairing vulnerable to MITM attacks, so this should only be used
+/// when the device has no way of displaying a random one.
+#[cfg(feature = "ble-sec")]
+pub fn set_static_passkey(_sd: &Softdevice, passkey: Option<&'static [u8; 6]>) -> Result<(), RawError> {
+    if let Some(passkey) = passkey {
+        if !passkey.iter().all(u8::is_ascii_digit) {
+            return Err(RawError::InvalidParam);
+        }
+    }
+
+    set_opt(
+        raw::BLE_GAP_OPTS_BLE_GAP_OPT_PASSKEY,
+        raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t {
+                passkey: raw::ble_gap_opt_passkey_t {
+                    p_passkey: passkey.map(|p| p.as_ptr()).unwrap_or(core::ptr::null()),
+                },
+            },
+        },
+    )
+}
+
+/// Set the authenticated payload timeout of an encrypted connection, in units of 10 ms.
+///
+/// The LE ping procedure is started before the timeout expires to give the peer a chance to reset it.
+/// The timeout should be longer than `2 * conn_interval * (6 + slave_latency)`.
+pub fn set_auth_payload_timeout(conn: &Connection, timeout: u16) -> Result<(), OptionError> {
+    let conn_handle = conn.with_state(|state| state.check_connected())?;
+    set_opt(
+        raw::BLE_GAP_OPTS_BLE_GAP_OPT_AUTH_PAYLOAD_TIMEOUT,
+        raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t {
+                auth_payload_timeout: raw::ble_gap_opt_auth_payload_timeout_t {
+                    conn_handle,
+                    auth_payload_timeout: timeout,
+                },
+            },
+        },
+    )?;
+    Ok(())
+}
+
+/// Get the authenticated payload timeout of the connection, in units of 10 ms.
+pub fn get_auth_payload_timeout(conn: &Connection) -> Result<u16, OptionError> {
+    let conn_handle = conn.with_state(|state| state.check_connected())?;
+    let opt = get_opt(
+        raw::BLE_GAP_OPTS_BLE_GAP_OPT_AUTH_PAYLOAD_TIMEOUT,
+        raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t {
+                auth_payload_timeout: raw::ble_gap_opt_auth_payload_timeout_t {
+                    conn_handle,
+                    auth_payload_timeout: 0,
+                },
+            },
+        },
+    )?;
+    Ok(unsafe { opt.gap_opt.auth_payload_timeout.auth_payload_timeout })
+}
+
+/// Enable compatibility mode 1, for interoperability with legacy peripherals that don't support
+/// a WinOffset of 0 in the connection request.
+///
+/// When enabled, creating connections as central can take up to one connection interval longer.
+/// Returns [`RawError::InvalidState`] if a connection is being created.
+#[cfg(all(feature = "ble-central", any(feature = "s122", feature = "s132", feature = "s140")))]
+pub fn set_compat_mode_1(_sd: &Softdevice, enable: bool) -> Result<(), RawError> {
+    set_opt(
+        raw::BLE_GAP_OPTS_BLE_GAP_OPT_COMPAT_MODE_1,
+        raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t {
+                compat_mode_1: raw::ble_gap_opt_compat_mode_1_t {
+                    _bitfield_1: raw::ble_gap_opt_compat_mode_1_t::new_bitfield_1(enable as u8),
+                },
+            },
+        },
+    )
+}
+
+/// Disable slave latency on a peripheral connection, waking up at every connection event, or re-enable it.
+///
+/// See [`Connection::ignore_slave_latency`].
+#[cfg(feature = "ble-peripheral")]
+pub fn set_slave_latency_disable(conn: &Connection, disable: bool) -> Result<(), OptionError> {
+    let conn_handle = conn.with_state(|state| state.check_connected())?;
+
+    let mut slave_latency_disable: raw::ble_gap_opt_slave_latency_disable_t = unsafe { core::mem::zeroed() };
+    slave_latency_disable.conn_handle = conn_handle;
+    slave_latency_disable.set_disable(disable as u8);
+
+    set_opt(
+        raw::BLE_GAP_OPTS_BLE_GAP_OPT_SLAVE_LATENCY_DISABLE,
+        raw::ble_opt_t {
+            gap_opt: raw::ble_gap_opt_t { slave_latency_disable },
+        },
+    )?;
+    Ok(())
+}
+
+/// Enable extended connection events.
+///
+/// When enabled, connection events are extended beyond the configured
+/// [`event_length`](raw::ble_gap_conn_cfg_t::event_length) when there's time to send another
+/// packet pair before the next connection interval, and no other role needs the radio.
+pub fn set_conn_evt_ext(_sd: &Softdevice, enable: bool) -> Result<(), RawError> {
+    set_opt(
+        raw::BLE_COMMON_OPTS_BLE_COMMON_OPT_CONN_EVT_EXT,
+        raw::ble_opt_t {
+            common_opt: raw::ble_common_opt_t {
+                conn_evt_ext: raw::ble_common_opt_conn_evt_ext_t {
+                    _bitfield_1: raw::ble_common_opt_conn_evt_ext_t::new_bitfield_1(enable as u8),
+                },
+            },
+        },
+    )
+}
@@ -219,6 +219,11 @@ impl From<RawError> for AdvertiseError {
 static mut ADV_HANDLE: u8 = raw::BLE_GAP_ADV_SET_HANDLE_NOT_SET as u8;
 pub(crate) static ADV_PORTAL: Portal<*const raw::ble_evt_t> = Portal::new();
 
+/// The advertising set is freed when the softdevice is disabled.
+pub(crate) fn on_softdevice_disabled() {
+    unsafe { ADV_HANDLE = raw::BLE_GAP_ADV_SET_HANDLE_NOT_SET as u8 };
+}
+
 fn start_adv(adv: RawAdvertisement<'_>, config: &Config) -> Result<(), AdvertiseError> {
     let mut adv_params: raw::ble_gap_adv_params_t = unsafe { core::mem::zeroed() };
 
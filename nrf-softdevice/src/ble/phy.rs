@@ -0,0 +1,83 @@
+//! PHY update request handling.
+//!
+//! When the peer requests a PHY update, the PHYs to reply with are chosen by the [`PhyPolicy`] set on
+//! the connection with [`Connection::set_phy_policy`], or else by the one set with [`set_default_policy`].
+//! Without any policy, the peer's preferred PHYs are used.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+
+use crate::ble::{Connection, PhySet};
+use crate::{raw, RawError};
+
+pub trait PhyPolicy {
+    /// Choose the PHYs to reply with to a PHY update request from the peer.
+    ///
+    /// `peer_tx` and `peer_rx` are the PHYs preferred by the peer, limited to those supported by the
+    /// softdevice, or all of them if the peer has no preference. Returns the `(tx, rx)` PHYs preferred
+    /// by us. The link layer then picks PHYs supported by both sides, or keeps the current ones if
+    /// there are none.
+    fn on_update_request(&self, conn: &Connection, peer_tx: PhySet, peer_rx: PhySet) -> (PhySet, PhySet);
+}
+
+/// Policy replying with a fixed set of PHYs, whatever the peer prefers.
+///
+/// For example, `FixedPhys(PhySet::M1)` refuses switching to 2M or Coded, and `FixedPhys(PhySet::Coded)`
+/// only allows Coded.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedPhys(pub PhySet);
+
+impl PhyPolicy for FixedPhys {
+    fn on_update_request(&self, _conn: &Connection, _peer_tx: PhySet, _peer_rx: PhySet) -> (PhySet, PhySet) {
+        (self.0, self.0)
+    }
+}
+
+static DEFAULT_POLICY: Mutex<CriticalSectionRawMutex, Cell<Option<&'static (dyn PhyPolicy + Sync)>>> =
+    Mutex::new(Cell::new(None));
+
+/// Set the policy used by connections which don't have their own.
+pub fn set_default_policy(policy: Option<&'static (dyn PhyPolicy + Sync)>) {
+    DEFAULT_POLICY.lock(|p| p.set(policy))
+}
+
+/// PHYs supported by the softdevice, as a `BLE_GAP_PHY_*` mask.
+#[cfg(feature = "s140")]
+const SUPPORTED_PHYS: u8 = (raw::BLE_GAP_PHY_1MBPS | raw::BLE_GAP_PHY_2MBPS | raw::BLE_GAP_PHY_CODED) as u8;
+#[cfg(not(feature = "s140"))]
+const SUPPORTED_PHYS: u8 = (raw::BLE_GAP_PHY_1MBPS | raw::BLE_GAP_PHY_2MBPS) as u8;
+
+/// Convert PHYs preferred by the peer, keeping only the supported ones. No preference means any PHY.
+fn peer_phys(phys: u8) -> PhySet {
+    let phys = match phys & SUPPORTED_PHYS {
+        0 => SUPPORTED_PHYS,
+        phys => phys,
+    };
+    unwrap!(PhySet::from_raw(phys))
+}
+
+pub(crate) fn on_update_request(conn_handle: u16, peer_preferred_phys: raw::ble_gap_phys_t) {
+    let mut phys = peer_preferred_phys;
+
+    if let Some(conn) = Connection::from_handle(conn_handle) {
+        let policy = conn
+            .phy_policy()
+            .or_else(|| DEFAULT_POLICY.lock(|p| p.get()).map(|p| p as &dyn PhyPolicy));
+        if let Some(policy) = policy {
+            let peer_tx = peer_phys(peer_preferred_phys.tx_phys);
+            let peer_rx = peer_phys(peer_preferred_phys.rx_phys);
+            let (tx, rx) = policy.on_update_request(&conn, peer_tx, peer_rx);
+            phys = raw::ble_gap_phys_t {
+                tx_phys: tx as u8,
+                rx_phys: rx as u8,
+            };
+        }
+    }
+
+    let ret = unsafe { raw::sd_ble_gap_phy_update(conn_handle, &phys) };
+    if let Err(_err) = RawError::convert(ret) {
+        warn!("sd_ble_gap_phy_update err {:?}", _err);
+    }
+}
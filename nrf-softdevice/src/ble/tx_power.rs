@@ -0,0 +1,155 @@
+//! Adaptive transmit power control.
+//!
+//! [`AdaptiveTxPower`] adjusts the transmit power of a connection from its RSSI measurements: when the
+//! peer is close and received strongly, the power is stepped down to save energy, and when the signal
+//! gets weak, it's stepped back up, between the configured bounds.
+//!
+//! This assumes the link is roughly symmetric, i.e. the peer receives us about as well as we receive it.
+
+use crate::ble::{Connection, DisconnectedError, LinkEvent, SetTxPowerError, TxPower};
+
+#[derive(Copy, Clone)]
+pub struct Config {
+    /// Lowest transmit power to use.
+    pub min_tx_power: TxPower,
+    /// Highest transmit power to use.
+    pub max_tx_power: TxPower,
+    /// Transmit power set when the controller starts, clamped to `min_tx_power..=max_tx_power`.
+    pub initial_tx_power: TxPower,
+
+    /// Step the power up when the RSSI is below this, in dBm.
+    pub rssi_low: i8,
+    /// Step the power down when the RSSI is above this, in dBm.
+    pub rssi_high: i8,
+
+    /// Number of consecutive RSSI samples below `rssi_low` or above `rssi_high` needed to change the power.
+    pub samples: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            min_tx_power: TxPower::Minus20dBm,
+            max_tx_power: TxPower::ZerodBm,
+            initial_tx_power: TxPower::ZerodBm,
+            rssi_low: -75,
+            rssi_high: -55,
+            samples: 8,
+        }
+    }
+}
+
+/// Error type for [`AdaptiveTxPower::new`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// `min_tx_power` is above `max_tx_power`.
+    InvalidTxPowerRange,
+    /// `rssi_low` is above `rssi_high`.
+    InvalidRssiRange,
+}
+
+pub struct AdaptiveTxPower {
+    config: Config,
+    tx_power: TxPower,
+    // Positive while the RSSI stays above `rssi_high`, negative while it stays below `rssi_low`.
+    count: i16,
+}
+
+impl AdaptiveTxPower {
+    pub fn new(mut config: Config) -> Result<Self, ConfigError> {
+        if config.min_tx_power as i8 > config.max_tx_power as i8 {
+            return Err(ConfigError::InvalidTxPowerRange);
+        }
+        if config.rssi_low > config.rssi_high {
+            return Err(ConfigError::InvalidRssiRange);
+        }
+        if (config.initial_tx_power as i8) < config.min_tx_power as i8 {
+            config.initial_tx_power = config.min_tx_power;
+        } else if config.initial_tx_power as i8 > config.max_tx_power as i8 {
+            config.initial_tx_power = config.max_tx_power;
+        }
+
+        Ok(Self {
+            config,
+            tx_power: config.initial_tx_power,
+            count: 0,
+        })
+    }
+
+    /// The transmit power currently chosen by the controller.
+    pub fn tx_power(&self) -> TxPower {
+        self.tx_power
+    }
+
+    /// Feed a new RSSI sample for the connection, changing its transmit power if needed.
+    ///
+    /// Returns the new transmit power if it was changed.
+    pub fn update(&mut self, conn: &Connection, rssi: i8) -> Result<Option<TxPower>, SetTxPowerError> {
+        let samples = i16::from(self.config.samples.max(1));
+
+        self.count = if rssi > self.config.rssi_high {
+            self.count.max(0) + 1
+        } else if rssi < self.config.rssi_low {
+            self.count.min(0) - 1
+        } else {
+            0
+        };
+
+        let next = if self.count >= samples {
+            self.tx_power
+                .step_down()
+                .filter(|&p| p as i8 >= self.config.min_tx_power as i8)
+        } else if self.count <= -samples {
+            self.tx_power
+                .step_up()
+                .filter(|&p| p as i8 <= self.config.max_tx_power as i8)
+        } else {
+            None
+        };
+
+        let Some(next) = next else {
+            self.count = self.count.clamp(-samples, samples);
+            return Ok(None);
+        };
+
+        debug!(
+            "rssi {:?}: tx power {:?} -> {:?}",
+            rssi, self.tx_power as i8, next as i8
+        );
+        conn.set_tx_power(next)?;
+        self.tx_power = next;
+        self.count = 0;
+        Ok(Some(next))
+    }
+
+    /// Control the transmit power of the connection until it is disconnected.
+    ///
+    /// This sets the initial transmit power, starts RSSI measurements and waits for them with
+    /// [`Connection::subscribe_link_events`]. Other tasks can still wait for changes on the same connection.
+    /// Use [`update`](Self::update) to feed RSSI samples from an existing loop instead.
+    pub async fn run(&mut self, conn: &Connection) -> DisconnectedError {
+        self.tx_power = self.config.initial_tx_power;
+        self.count = 0;
+        let mut events = match conn.subscribe_link_events() {
+            Ok(events) => events,
+            Err(err) => return err,
+        };
+        if let Err(SetTxPowerError::Disconnected) = conn.set_tx_power(self.tx_power) {
+            return DisconnectedError;
+        }
+        conn.start_rssi();
+
+        loop {
+            match events.next().await {
+                Ok(LinkEvent::Rssi(rssi)) => {
+                    if let Err(SetTxPowerError::Disconnected) = self.update(conn, rssi) {
+                        return DisconnectedError;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => return err,
+            }
+        }
+    }
+}
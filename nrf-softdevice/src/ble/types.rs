@@ -260,8 +260,143 @@ pub enum TxPower {
     Plus8dBm = 8,
 }
 
+impl TxPower {
+    /// All the transmit power levels supported by the softdevice, from lowest to highest.
+    pub const ALL: &'static [TxPower] = &[
+        TxPower::Minus40dBm,
+        TxPower::Minus20dBm,
+        TxPower::Minus16dBm,
+        TxPower::Minus12dBm,
+        TxPower::Minus8dBm,
+        TxPower::Minus4dBm,
+        TxPower::ZerodBm,
+        #[cfg(feature = "s140")]
+        TxPower::Plus2dBm,
+        TxPower::Plus3dBm,
+        TxPower::Plus4dBm,
+        #[cfg(feature = "s140")]
+        TxPower::Plus5dBm,
+        #[cfg(feature = "s140")]
+        TxPower::Plus6dBm,
+        #[cfg(feature = "s140")]
+        TxPower::Plus7dBm,
+        #[cfg(feature = "s140")]
+        TxPower::Plus8dBm,
+    ];
+
+    /// The next higher transmit power level, if any.
+    pub fn step_up(self) -> Option<TxPower> {
+        let i = Self::ALL.iter().position(|&p| p == self)?;
+        Self::ALL.get(i + 1).copied()
+    }
+
+    /// The next lower transmit power level, if any.
+    pub fn step_down(self) -> Option<TxPower> {
+        let i = Self::ALL.iter().position(|&p| p == self)?;
+        i.checked_sub(1).map(|i| Self::ALL[i])
+    }
+}
+
+/// Appearance of the device, exposed in the GAP service and in advertisements.
+///
+/// The upper 10 bits are the category, the lower 6 bits the subcategory.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Eq, PartialEq, Copy, Clone)]
+pub struct Appearance(u16);
+
+impl Appearance {
+    pub const UNKNOWN: Appearance = Appearance(raw::BLE_APPEARANCE_UNKNOWN as u16);
+    pub const GENERIC_PHONE: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_PHONE as u16);
+    pub const GENERIC_COMPUTER: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_COMPUTER as u16);
+    pub const GENERIC_WATCH: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_WATCH as u16);
+    pub const WATCH_SPORTS_WATCH: Appearance = Appearance(raw::BLE_APPEARANCE_WATCH_SPORTS_WATCH as u16);
+    pub const GENERIC_CLOCK: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_CLOCK as u16);
+    pub const GENERIC_DISPLAY: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_DISPLAY as u16);
+    pub const GENERIC_REMOTE_CONTROL: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_REMOTE_CONTROL as u16);
+    pub const GENERIC_EYE_GLASSES: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_EYE_GLASSES as u16);
+    pub const GENERIC_TAG: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_TAG as u16);
+    pub const GENERIC_KEYRING: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_KEYRING as u16);
+    pub const GENERIC_MEDIA_PLAYER: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_MEDIA_PLAYER as u16);
+    pub const GENERIC_BARCODE_SCANNER: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_BARCODE_SCANNER as u16);
+    pub const GENERIC_THERMOMETER: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_THERMOMETER as u16);
+    pub const THERMOMETER_EAR: Appearance = Appearance(raw::BLE_APPEARANCE_THERMOMETER_EAR as u16);
+    pub const GENERIC_HEART_RATE_SENSOR: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_HEART_RATE_SENSOR as u16);
+    pub const HEART_RATE_SENSOR_HEART_RATE_BELT: Appearance =
+        Appearance(raw::BLE_APPEARANCE_HEART_RATE_SENSOR_HEART_RATE_BELT as u16);
+    pub const GENERIC_BLOOD_PRESSURE: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_BLOOD_PRESSURE as u16);
+    pub const BLOOD_PRESSURE_ARM: Appearance = Appearance(raw::BLE_APPEARANCE_BLOOD_PRESSURE_ARM as u16);
+    pub const BLOOD_PRESSURE_WRIST: Appearance = Appearance(raw::BLE_APPEARANCE_BLOOD_PRESSURE_WRIST as u16);
+    pub const GENERIC_HID: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_HID as u16);
+    pub const HID_KEYBOARD: Appearance = Appearance(raw::BLE_APPEARANCE_HID_KEYBOARD as u16);
+    pub const HID_MOUSE: Appearance = Appearance(raw::BLE_APPEARANCE_HID_MOUSE as u16);
+    pub const HID_JOYSTICK: Appearance = Appearance(raw::BLE_APPEARANCE_HID_JOYSTICK as u16);
+    pub const HID_GAMEPAD: Appearance = Appearance(raw::BLE_APPEARANCE_HID_GAMEPAD as u16);
+    pub const HID_DIGITIZERSUBTYPE: Appearance = Appearance(raw::BLE_APPEARANCE_HID_DIGITIZERSUBTYPE as u16);
+    pub const HID_CARD_READER: Appearance = Appearance(raw::BLE_APPEARANCE_HID_CARD_READER as u16);
+    pub const HID_DIGITAL_PEN: Appearance = Appearance(raw::BLE_APPEARANCE_HID_DIGITAL_PEN as u16);
+    pub const HID_BARCODE: Appearance = Appearance(raw::BLE_APPEARANCE_HID_BARCODE as u16);
+    pub const GENERIC_GLUCOSE_METER: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_GLUCOSE_METER as u16);
+    pub const GENERIC_RUNNING_WALKING_SENSOR: Appearance =
+        Appearance(raw::BLE_APPEARANCE_GENERIC_RUNNING_WALKING_SENSOR as u16);
+    pub const RUNNING_WALKING_SENSOR_IN_SHOE: Appearance =
+        Appearance(raw::BLE_APPEARANCE_RUNNING_WALKING_SENSOR_IN_SHOE as u16);
+    pub const RUNNING_WALKING_SENSOR_ON_SHOE: Appearance =
+        Appearance(raw::BLE_APPEARANCE_RUNNING_WALKING_SENSOR_ON_SHOE as u16);
+    pub const RUNNING_WALKING_SENSOR_ON_HIP: Appearance =
+        Appearance(raw::BLE_APPEARANCE_RUNNING_WALKING_SENSOR_ON_HIP as u16);
+    pub const GENERIC_CYCLING: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_CYCLING as u16);
+    pub const CYCLING_CYCLING_COMPUTER: Appearance = Appearance(raw::BLE_APPEARANCE_CYCLING_CYCLING_COMPUTER as u16);
+    pub const CYCLING_SPEED_SENSOR: Appearance = Appearance(raw::BLE_APPEARANCE_CYCLING_SPEED_SENSOR as u16);
+    pub const CYCLING_CADENCE_SENSOR: Appearance = Appearance(raw::BLE_APPEARANCE_CYCLING_CADENCE_SENSOR as u16);
+    pub const CYCLING_POWER_SENSOR: Appearance = Appearance(raw::BLE_APPEARANCE_CYCLING_POWER_SENSOR as u16);
+    pub const CYCLING_SPEED_CADENCE_SENSOR: Appearance =
+        Appearance(raw::BLE_APPEARANCE_CYCLING_SPEED_CADENCE_SENSOR as u16);
+    pub const GENERIC_PULSE_OXIMETER: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_PULSE_OXIMETER as u16);
+    pub const PULSE_OXIMETER_FINGERTIP: Appearance = Appearance(raw::BLE_APPEARANCE_PULSE_OXIMETER_FINGERTIP as u16);
+    pub const PULSE_OXIMETER_WRIST_WORN: Appearance = Appearance(raw::BLE_APPEARANCE_PULSE_OXIMETER_WRIST_WORN as u16);
+    pub const GENERIC_WEIGHT_SCALE: Appearance = Appearance(raw::BLE_APPEARANCE_GENERIC_WEIGHT_SCALE as u16);
+    pub const GENERIC_OUTDOOR_SPORTS_ACT: Appearance =
+        Appearance(raw::BLE_APPEARANCE_GENERIC_OUTDOOR_SPORTS_ACT as u16);
+    pub const OUTDOOR_SPORTS_ACT_LOC_DISP: Appearance =
+        Appearance(raw::BLE_APPEARANCE_OUTDOOR_SPORTS_ACT_LOC_DISP as u16);
+    pub const OUTDOOR_SPORTS_ACT_LOC_AND_NAV_DISP: Appearance =
+        Appearance(raw::BLE_APPEARANCE_OUTDOOR_SPORTS_ACT_LOC_AND_NAV_DISP as u16);
+    pub const OUTDOOR_SPORTS_ACT_LOC_POD: Appearance =
+        Appearance(raw::BLE_APPEARANCE_OUTDOOR_SPORTS_ACT_LOC_POD as u16);
+    pub const OUTDOOR_SPORTS_ACT_LOC_AND_NAV_POD: Appearance =
+        Appearance(raw::BLE_APPEARANCE_OUTDOOR_SPORTS_ACT_LOC_AND_NAV_POD as u16);
+
+    pub const fn from_u16(value: u16) -> Self {
+        Appearance(value)
+    }
+
+    pub const fn to_u16(self) -> u16 {
+        self.0
+    }
+
+    pub const fn category(self) -> u16 {
+        self.0 >> 6
+    }
+
+    pub const fn subcategory(self) -> u8 {
+        (self.0 & 0x3f) as u8
+    }
+}
+
+impl From<u16> for Appearance {
+    fn from(value: u16) -> Self {
+        Appearance(value)
+    }
+}
+
+impl From<Appearance> for u16 {
+    fn from(value: Appearance) -> Self {
+        value.0
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[repr(u8)]
 pub enum Phy {
     /// 1Mbps phy
@@ -273,8 +408,20 @@ pub enum Phy {
     Coded = 4,
 }
 
+impl Phy {
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        match u32::from(raw) {
+            raw::BLE_GAP_PHY_1MBPS => Some(Phy::M1),
+            raw::BLE_GAP_PHY_2MBPS => Some(Phy::M2),
+            #[cfg(feature = "s140")]
+            raw::BLE_GAP_PHY_CODED => Some(Phy::Coded),
+            _ => None,
+        }
+    }
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[repr(u8)]
 pub enum PhySet {
     /// 1Mbps phy
@@ -297,6 +444,25 @@ pub enum PhySet {
     M1M2Coded = 7,
 }
 
+impl PhySet {
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            1 => Some(PhySet::M1),
+            2 => Some(PhySet::M2),
+            3 => Some(PhySet::M1M2),
+            #[cfg(feature = "s140")]
+            4 => Some(PhySet::Coded),
+            #[cfg(feature = "s140")]
+            5 => Some(PhySet::M1Coded),
+            #[cfg(feature = "s140")]
+            6 => Some(PhySet::M2Coded),
+            #[cfg(feature = "s140")]
+            7 => Some(PhySet::M1M2Coded),
+            _ => None,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
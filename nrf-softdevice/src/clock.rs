@@ -0,0 +1,117 @@
+//! High frequency clock control.
+//!
+//! While the softdevice is enabled, the external high frequency crystal (HFXO) must be requested
+//! through the softdevice. [`HfclkGuard`]s keep it running while any of them is alive.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::Poll;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use futures::future::poll_fn;
+
+use crate::{raw, RawError, SocEvent, Softdevice};
+
+static USERS: AtomicUsize = AtomicUsize::new(0);
+/// Whether the crystal is currently requested from the softdevice.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static WAKERS: Mutex<CriticalSectionRawMutex, RefCell<MultiWakerRegistration<4>>> =
+    Mutex::new(RefCell::new(MultiWakerRegistration::new()));
+
+/// Keeps the external high frequency crystal running while alive.
+///
+/// Guards are reference counted: the crystal is requested when the first one is created and
+/// released when the last one is dropped.
+pub struct HfclkGuard {
+    _private: (),
+}
+
+impl HfclkGuard {
+    /// Request the external crystal and wait until it's running.
+    ///
+    /// [`Softdevice::run`] must be running to get notified when the crystal has started.
+    pub async fn request(sd: &Softdevice) -> Result<Self, RawError> {
+        USERS.fetch_add(1, Ordering::AcqRel);
+        // From here on, dropping the guard releases the crystal, including if this future is dropped.
+        let guard = HfclkGuard { _private: () };
+
+        poll_fn(|cx| {
+            WAKERS.lock(|w| w.borrow_mut().register(cx.waker()));
+            // Every waiter (re-)issues the request if it's not outstanding, so they don't wait forever
+            // when another guard's request failed or the last guard released the crystal meanwhile.
+            if let Err(err) = ensure_requested() {
+                return Poll::Ready(Err(err));
+            }
+            match hfclk_is_running(sd) {
+                Ok(true) => Poll::Ready(Ok(())),
+                Ok(false) => Poll::Pending,
+                Err(err) => Poll::Ready(Err(err)),
+            }
+        })
+        .await?;
+
+        Ok(guard)
+    }
+}
+
+impl Drop for HfclkGuard {
+    fn drop(&mut self) {
+        if USERS.fetch_sub(1, Ordering::AcqRel) == 1 && REQUESTED.load(Ordering::Acquire) {
+            let ret = unsafe { raw::sd_clock_hfclk_release() };
+            if let Err(_err) = RawError::convert(ret) {
+                warn!("sd_clock_hfclk_release err {:?}", _err);
+            }
+            REQUESTED.store(false, Ordering::Release);
+
+            // A new guard may have seen the crystal as requested before we released it.
+            if USERS.load(Ordering::Acquire) != 0 {
+                WAKERS.lock(|w| w.borrow_mut().wake());
+            }
+        }
+    }
+}
+
+fn ensure_requested() -> Result<(), RawError> {
+    if REQUESTED.swap(true, Ordering::AcqRel) {
+        return Ok(());
+    }
+    if let Err(err) = request() {
+        REQUESTED.store(false, Ordering::Release);
+        // Other guards may be waiting on this request, let them retry it.
+        WAKERS.lock(|w| w.borrow_mut().wake());
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn request() -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_clock_hfclk_request() };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_clock_hfclk_request err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Whether the external high frequency crystal is running.
+pub fn hfclk_is_running(_sd: &Softdevice) -> Result<bool, RawError> {
+    let mut is_running: u32 = 0;
+    let ret = unsafe { raw::sd_clock_hfclk_is_running(&mut is_running) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_clock_hfclk_is_running err {:?}", err);
+        return Err(err);
+    }
+    Ok(is_running != 0)
+}
+
+pub(crate) fn is_requested() -> bool {
+    USERS.load(Ordering::Acquire) != 0
+}
+
+pub(crate) fn on_soc_evt(evt: SocEvent) {
+    if evt == SocEvent::Hfclkstarted {
+        WAKERS.lock(|w| w.borrow_mut().wake());
+    }
+}
@@ -0,0 +1,477 @@
+use crate::ble::SecurityMode;
+use crate::{raw, Config};
+
+/// Low frequency clock source.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LfClock {
+    /// Internal RC oscillator, calibrated against the HFCLK.
+    Rc {
+        /// Calibration timer interval, in 1/4 second units (1..=32).
+        ctiv: u8,
+        /// How often (in number of calibration intervals) to calibrate if the temperature
+        /// hasn't changed (0 or 2..=33, 0 meaning always).
+        temp_ctiv: u8,
+    },
+    /// External 32.768 kHz crystal.
+    Xtal(LfClockAccuracy),
+    /// Synthesized from the HFCLK.
+    Synth(LfClockAccuracy),
+}
+
+impl LfClock {
+    /// RC oscillator with the calibration settings recommended by Nordic for nRF52.
+    pub const RC: Self = Self::Rc { ctiv: 16, temp_ctiv: 2 };
+
+    fn to_raw(self) -> raw::nrf_clock_lf_cfg_t {
+        match self {
+            Self::Rc { ctiv, temp_ctiv } => raw::nrf_clock_lf_cfg_t {
+                source: raw::NRF_CLOCK_LF_SRC_RC as u8,
+                rc_ctiv: ctiv,
+                rc_temp_ctiv: temp_ctiv,
+                accuracy: raw::NRF_CLOCK_LF_ACCURACY_500_PPM as u8,
+            },
+            Self::Xtal(accuracy) => raw::nrf_clock_lf_cfg_t {
+                source: raw::NRF_CLOCK_LF_SRC_XTAL as u8,
+                rc_ctiv: 0,
+                rc_temp_ctiv: 0,
+                accuracy: accuracy as u8,
+            },
+            Self::Synth(accuracy) => raw::nrf_clock_lf_cfg_t {
+                source: raw::NRF_CLOCK_LF_SRC_SYNTH as u8,
+                rc_ctiv: 0,
+                rc_temp_ctiv: 0,
+                accuracy: accuracy as u8,
+            },
+        }
+    }
+}
+
+/// Accuracy of the low frequency clock.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum LfClockAccuracy {
+    Ppm500 = raw::NRF_CLOCK_LF_ACCURACY_500_PPM as u8,
+    Ppm250 = raw::NRF_CLOCK_LF_ACCURACY_250_PPM as u8,
+    Ppm150 = raw::NRF_CLOCK_LF_ACCURACY_150_PPM as u8,
+    Ppm100 = raw::NRF_CLOCK_LF_ACCURACY_100_PPM as u8,
+    Ppm75 = raw::NRF_CLOCK_LF_ACCURACY_75_PPM as u8,
+    Ppm50 = raw::NRF_CLOCK_LF_ACCURACY_50_PPM as u8,
+    Ppm30 = raw::NRF_CLOCK_LF_ACCURACY_30_PPM as u8,
+    Ppm20 = raw::NRF_CLOCK_LF_ACCURACY_20_PPM as u8,
+    Ppm10 = raw::NRF_CLOCK_LF_ACCURACY_10_PPM as u8,
+    Ppm5 = raw::NRF_CLOCK_LF_ACCURACY_5_PPM as u8,
+    Ppm2 = raw::NRF_CLOCK_LF_ACCURACY_2_PPM as u8,
+    Ppm1 = raw::NRF_CLOCK_LF_ACCURACY_1_PPM as u8,
+}
+
+/// L2CAP configuration, per connection.
+#[cfg(feature = "ble-l2cap")]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct L2capConfig {
+    /// Number of L2CAP channels per connection.
+    pub ch_count: u8,
+    pub rx_mps: u16,
+    pub tx_mps: u16,
+    pub rx_queue_size: u8,
+    pub tx_queue_size: u8,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// RC calibration intervals are out of range.
+    LfClockCalibration,
+    /// More connections than the softdevice supports.
+    TooManyConnections,
+    /// More central security instances than central connections.
+    TooManyCentralSecurity,
+    /// Event length is below `BLE_GAP_EVENT_LENGTH_MIN`.
+    EventLengthTooShort,
+    /// ATT MTU is below `BLE_GATT_ATT_MTU_DEFAULT`.
+    AttMtuTooSmall,
+    /// Attribute table size is below `BLE_GATTS_ATTR_TAB_SIZE_MIN` or not a multiple of 4.
+    InvalidAttrTabSize,
+    /// More vendor specific UUIDs than `BLE_UUID_VS_COUNT_MAX`.
+    TooManyVsUuids,
+    /// Device name is longer than its maximum length, or the maximum length is above `BLE_GAP_DEVNAME_MAX_LEN`.
+    DeviceNameTooLong,
+    /// Invalid L2CAP MPS, queue size or channel count.
+    #[cfg(feature = "ble-l2cap")]
+    InvalidL2cap,
+}
+
+/// Typed builder for the softdevice [`Config`].
+///
+/// Unset values use the softdevice defaults.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigBuilder {
+    lf_clock: Option<LfClock>,
+    #[cfg(feature = "ble-peripheral")]
+    periph_count: u8,
+    #[cfg(feature = "ble-central")]
+    central_count: u8,
+    #[cfg(feature = "ble-central")]
+    central_sec_count: u8,
+    event_length: u16,
+    att_mtu: u16,
+    hvn_tx_queue_size: u8,
+    write_cmd_tx_queue_size: u8,
+    #[cfg(feature = "ble-l2cap")]
+    l2cap: Option<L2capConfig>,
+    attr_tab_size: u32,
+    vs_uuid_count: u8,
+    device_name: Option<&'static str>,
+    device_name_max_len: u16,
+    service_changed: bool,
+    #[cfg(any(feature = "s132", feature = "s140"))]
+    qos_channel_survey: bool,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Rough per-item RAM costs used by `ConfigBuilder::ram_estimate`. They are approximations,
+// only meant to size the RAM start address before the first run.
+const RAM_BASE: u32 = 0x1600;
+const RAM_PER_LINK: u32 = 1280;
+const RAM_PER_CENTRAL_SEC: u32 = 56;
+const RAM_PER_TX_QUEUE_ENTRY: u32 = 40;
+const RAM_PER_VS_UUID: u32 = 16;
+#[cfg(feature = "ble-l2cap")]
+const RAM_PER_L2CAP_CHANNEL: u32 = 64;
+
+impl ConfigBuilder {
+    pub const fn new() -> Self {
+        Self {
+            lf_clock: None,
+            #[cfg(feature = "ble-peripheral")]
+            periph_count: raw::BLE_GAP_ROLE_COUNT_PERIPH_DEFAULT as u8,
+            #[cfg(feature = "ble-central")]
+            central_count: raw::BLE_GAP_ROLE_COUNT_CENTRAL_DEFAULT as u8,
+            #[cfg(feature = "ble-central")]
+            central_sec_count: raw::BLE_GAP_ROLE_COUNT_CENTRAL_SEC_DEFAULT as u8,
+            event_length: raw::BLE_GAP_EVENT_LENGTH_DEFAULT as u16,
+            att_mtu: raw::BLE_GATT_ATT_MTU_DEFAULT as u16,
+            hvn_tx_queue_size: raw::BLE_GATTS_HVN_TX_QUEUE_SIZE_DEFAULT as u8,
+            write_cmd_tx_queue_size: raw::BLE_GATTC_WRITE_CMD_TX_QUEUE_SIZE_DEFAULT as u8,
+            #[cfg(feature = "ble-l2cap")]
+            l2cap: None,
+            attr_tab_size: raw::BLE_GATTS_ATTR_TAB_SIZE_DEFAULT,
+            vs_uuid_count: raw::BLE_UUID_VS_COUNT_DEFAULT as u8,
+            device_name: None,
+            device_name_max_len: raw::BLE_GAP_DEVNAME_DEFAULT_LEN as u16,
+            service_changed: raw::BLE_GATTS_SERVICE_CHANGED_DEFAULT != 0,
+            #[cfg(any(feature = "s132", feature = "s140"))]
+            qos_channel_survey: false,
+        }
+    }
+
+    /// Low frequency clock source. If not set, the softdevice default is used.
+    pub const fn lf_clock(mut self, lf_clock: LfClock) -> Self {
+        self.lf_clock = Some(lf_clock);
+        self
+    }
+
+    /// Maximum number of concurrent connections as a peripheral.
+    #[cfg(feature = "ble-peripheral")]
+    pub const fn periph_count(mut self, count: u8) -> Self {
+        self.periph_count = count;
+        self
+    }
+
+    /// Maximum number of concurrent connections as a central.
+    #[cfg(feature = "ble-central")]
+    pub const fn central_count(mut self, count: u8) -> Self {
+        self.central_count = count;
+        self
+    }
+
+    /// Number of security (SMP) instances shared between central connections.
+    #[cfg(feature = "ble-central")]
+    pub const fn central_sec_count(mut self, count: u8) -> Self {
+        self.central_sec_count = count;
+        self
+    }
+
+    /// Connection event length, in 1.25 ms units.
+    pub const fn event_length(mut self, event_length: u16) -> Self {
+        self.event_length = event_length;
+        self
+    }
+
+    /// Maximum ATT MTU.
+    pub const fn att_mtu(mut self, att_mtu: u16) -> Self {
+        self.att_mtu = att_mtu;
+        self
+    }
+
+    /// Number of queued notifications per connection.
+    pub const fn hvn_tx_queue_size(mut self, size: u8) -> Self {
+        self.hvn_tx_queue_size = size;
+        self
+    }
+
+    /// Number of queued write commands per connection.
+    pub const fn write_cmd_tx_queue_size(mut self, size: u8) -> Self {
+        self.write_cmd_tx_queue_size = size;
+        self
+    }
+
+    #[cfg(feature = "ble-l2cap")]
+    pub const fn l2cap(mut self, l2cap: L2capConfig) -> Self {
+        self.l2cap = Some(l2cap);
+        self
+    }
+
+    /// Attribute table size in bytes, see [`gatt_server::attr_tab_size`](crate::ble::gatt_server::attr_tab_size).
+    pub const fn attr_tab_size(mut self, size: u32) -> Self {
+        self.attr_tab_size = size;
+        self
+    }
+
+    /// Number of vendor specific UUID bases.
+    pub const fn vs_uuid_count(mut self, count: u8) -> Self {
+        self.vs_uuid_count = count;
+        self
+    }
+
+    /// Initial GAP device name. It is copied by the softdevice, and is not writable by the peer.
+    pub const fn device_name(mut self, name: &'static str) -> Self {
+        self.device_name = Some(name);
+        self
+    }
+
+    /// Maximum length of the GAP device name, for names set later with `sd_ble_gap_device_name_set`.
+    /// Defaults to `BLE_GAP_DEVNAME_DEFAULT_LEN`. Names longer than the default take attribute table space.
+    /// Without [`device_name`](Self::device_name), the softdevice default name is kept.
+    pub const fn device_name_max_len(mut self, max_len: u16) -> Self {
+        self.device_name_max_len = max_len;
+        self
+    }
+
+    /// Include the Service Changed characteristic in the attribute table.
+    pub const fn service_changed(mut self, enabled: bool) -> Self {
+        self.service_changed = enabled;
+        self
+    }
+
+    /// Make the QoS channel survey role available, see [`channel_survey`](crate::ble::channel_survey).
+    #[cfg(any(feature = "s132", feature = "s140"))]
+    pub const fn qos_channel_survey(mut self, enabled: bool) -> Self {
+        self.qos_channel_survey = enabled;
+        self
+    }
+
+    fn link_count(&self) -> u32 {
+        #[allow(unused_mut)]
+        let mut count = 0;
+        #[cfg(feature = "ble-peripheral")]
+        {
+            count += u32::from(self.periph_count);
+        }
+        #[cfg(feature = "ble-central")]
+        {
+            count += u32::from(self.central_count);
+        }
+        count
+    }
+
+    /// Check the configuration against the limits of the selected softdevice.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(LfClock::Rc { ctiv, temp_ctiv }) = self.lf_clock {
+            if !(1..=32).contains(&ctiv) || temp_ctiv == 1 || temp_ctiv > 33 {
+                return Err(ConfigError::LfClockCalibration);
+            }
+        }
+
+        if self.link_count() > raw::BLE_GAP_ROLE_COUNT_COMBINED_MAX {
+            return Err(ConfigError::TooManyConnections);
+        }
+
+        #[cfg(feature = "ble-central")]
+        if self.central_sec_count > self.central_count {
+            return Err(ConfigError::TooManyCentralSecurity);
+        }
+
+        if u32::from(self.event_length) < raw::BLE_GAP_EVENT_LENGTH_MIN {
+            return Err(ConfigError::EventLengthTooShort);
+        }
+
+        if u32::from(self.att_mtu) < raw::BLE_GATT_ATT_MTU_DEFAULT {
+            return Err(ConfigError::AttMtuTooSmall);
+        }
+
+        if self.attr_tab_size < raw::BLE_GATTS_ATTR_TAB_SIZE_MIN || self.attr_tab_size % 4 != 0 {
+            return Err(ConfigError::InvalidAttrTabSize);
+        }
+
+        if u32::from(self.vs_uuid_count) > raw::BLE_UUID_VS_COUNT_MAX {
+            return Err(ConfigError::TooManyVsUuids);
+        }
+
+        if u32::from(self.device_name_max_len) > raw::BLE_GAP_DEVNAME_MAX_LEN {
+            return Err(ConfigError::DeviceNameTooLong);
+        }
+
+        if let Some(name) = self.device_name {
+            if name.len() > usize::from(self.device_name_max_len) {
+                return Err(ConfigError::DeviceNameTooLong);
+            }
+        }
+
+        #[cfg(feature = "ble-l2cap")]
+        if let Some(l2cap) = self.l2cap {
+            if u32::from(l2cap.ch_count) > raw::BLE_L2CAP_CH_COUNT_MAX
+                || u32::from(l2cap.rx_mps) < raw::BLE_L2CAP_MPS_MIN
+                || u32::from(l2cap.tx_mps) < raw::BLE_L2CAP_MPS_MIN
+                || l2cap.rx_queue_size == 0
+                || l2cap.tx_queue_size == 0
+            {
+                return Err(ConfigError::InvalidL2cap);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Estimate the RAM needed by the softdevice with this configuration, in bytes.
+    ///
+    /// This is an approximation, useful to pick the RAM start address in `memory.x` before the first run.
+    /// The exact requirement is logged by [`Softdevice::enable`](crate::Softdevice::enable).
+    pub fn ram_estimate(&self) -> u32 {
+        let links = self.link_count();
+
+        let per_link = RAM_PER_LINK
+            + 2 * u32::from(self.att_mtu)
+            + RAM_PER_TX_QUEUE_ENTRY * (u32::from(self.hvn_tx_queue_size) + u32::from(self.write_cmd_tx_queue_size))
+            + self.l2cap_ram_per_link();
+
+        let mut total = RAM_BASE + links * per_link;
+        #[cfg(feature = "ble-central")]
+        {
+            total += RAM_PER_CENTRAL_SEC * u32::from(self.central_sec_count);
+        }
+        total += self.attr_tab_size;
+        total += RAM_PER_VS_UUID * u32::from(self.vs_uuid_count);
+        if self.has_device_name_cfg() {
+            total += u32::from(self.device_name_max_len);
+        }
+
+        // The softdevice hands out RAM in 8-byte aligned chunks.
+        (total + 7) & !7
+    }
+
+    /// Whether the GAP device name needs configuring, rather than using the softdevice default.
+    fn has_device_name_cfg(&self) -> bool {
+        self.device_name.is_some() || u32::from(self.device_name_max_len) != raw::BLE_GAP_DEVNAME_DEFAULT_LEN
+    }
+
+    #[cfg(feature = "ble-l2cap")]
+    fn l2cap_ram_per_link(&self) -> u32 {
+        match self.l2cap {
+            Some(l2cap) => {
+                u32::from(l2cap.ch_count)
+                    * (RAM_PER_L2CAP_CHANNEL
+                        + u32::from(l2cap.rx_queue_size) * u32::from(l2cap.rx_mps)
+                        + u32::from(l2cap.tx_queue_size) * u32::from(l2cap.tx_mps))
+            }
+            None => 0,
+        }
+    }
+
+    #[cfg(not(feature = "ble-l2cap"))]
+    fn l2cap_ram_per_link(&self) -> u32 {
+        0
+    }
+
+    /// Validate the configuration and convert it to a [`Config`] for [`Softdevice::enable`](crate::Softdevice::enable).
+    pub fn build(&self) -> Result<Config, ConfigError> {
+        self.validate()?;
+
+        let role_count = raw::ble_gap_cfg_role_count_t {
+            #[cfg(any(feature = "s112", feature = "s113", feature = "s132", feature = "s140"))]
+            adv_set_count: raw::BLE_GAP_ADV_SET_COUNT_DEFAULT as u8,
+            #[cfg(all(feature = "ble-peripheral", not(feature = "s122")))]
+            periph_role_count: self.periph_count,
+            #[cfg(all(not(feature = "ble-peripheral"), not(feature = "s122")))]
+            periph_role_count: 0,
+            #[cfg(feature = "ble-central")]
+            central_role_count: self.central_count,
+            #[cfg(feature = "ble-central")]
+            central_sec_count: self.central_sec_count,
+            #[cfg(all(
+                not(feature = "ble-central"),
+                any(feature = "s122", feature = "s132", feature = "s140")
+            ))]
+            central_role_count: 0,
+            #[cfg(all(
+                not(feature = "ble-central"),
+                any(feature = "s122", feature = "s132", feature = "s140")
+            ))]
+            central_sec_count: 0,
+            #[cfg(any(feature = "s132", feature = "s140"))]
+            _bitfield_1: raw::ble_gap_cfg_role_count_t::new_bitfield_1(self.qos_channel_survey as u8),
+            #[cfg(feature = "s122")]
+            _bitfield_1: raw::ble_gap_cfg_role_count_t::new_bitfield_1(0),
+        };
+
+        Ok(Config {
+            clock: self.lf_clock.map(LfClock::to_raw),
+            conn_gap: Some(raw::ble_gap_conn_cfg_t {
+                conn_count: self.link_count() as u8,
+                event_length: self.event_length,
+            }),
+            conn_gattc: Some(raw::ble_gattc_conn_cfg_t {
+                write_cmd_tx_queue_size: self.write_cmd_tx_queue_size,
+            }),
+            conn_gatts: Some(raw::ble_gatts_conn_cfg_t {
+                hvn_tx_queue_size: self.hvn_tx_queue_size,
+            }),
+            conn_gatt: Some(raw::ble_gatt_conn_cfg_t { att_mtu: self.att_mtu }),
+            #[cfg(feature = "ble-l2cap")]
+            conn_l2cap: self.l2cap.map(|l2cap| raw::ble_l2cap_conn_cfg_t {
+                rx_mps: l2cap.rx_mps,
+                tx_mps: l2cap.tx_mps,
+                rx_queue_size: l2cap.rx_queue_size,
+                tx_queue_size: l2cap.tx_queue_size,
+                ch_count: l2cap.ch_count,
+            }),
+            common_vs_uuid: Some(raw::ble_common_cfg_vs_uuid_t {
+                vs_uuid_count: self.vs_uuid_count,
+            }),
+            gap_role_count: Some(role_count),
+            gap_device_name: self.has_device_name_cfg().then(|| {
+                let name = match self.device_name {
+                    Some(name) => name.as_bytes(),
+                    // Keep the softdevice's default name, without the NUL terminator.
+                    None => {
+                        let name = &raw::BLE_GAP_DEVNAME_DEFAULT[..raw::BLE_GAP_DEVNAME_DEFAULT.len() - 1];
+                        &name[..name.len().min(usize::from(self.device_name_max_len))]
+                    }
+                };
+                raw::ble_gap_cfg_device_name_t {
+                    p_value: name.as_ptr() as _,
+                    current_len: name.len() as u16,
+                    max_len: self.device_name_max_len,
+                    // The peer can't write the device name.
+                    write_perm: SecurityMode::NoAccess.into_raw(),
+                    _bitfield_1: raw::ble_gap_cfg_device_name_t::new_bitfield_1(raw::BLE_GATTS_VLOC_STACK as u8),
+                }
+            }),
+            gap_ppcp_incl: None,
+            gap_car_incl: None,
+            gatts_service_changed: Some(raw::ble_gatts_cfg_service_changed_t {
+                _bitfield_1: raw::ble_gatts_cfg_service_changed_t::new_bitfield_1(self.service_changed as u8),
+            }),
+            gatts_attr_tab_size: Some(raw::ble_gatts_cfg_attr_tab_size_t {
+                attr_tab_size: self.attr_tab_size,
+            }),
+        })
+    }
+}
@@ -0,0 +1,750 @@
+//! Enhanced ShockBurst running in radio timeslots.
+//!
+//! This implements Nordic's Enhanced ShockBurst (ESB) protocol with dynamic payload length, compatible
+//! with legacy nRF24 devices, using the RADIO and TIMER0 peripherals granted to a
+//! [`TimeslotSession`]. It can run alongside BLE, but only receives and transmits during timeslots.
+//!
+//! - As PTX, packets are transmitted from the TX queue, waiting for an ACK from the receiver and
+//!   retransmitting them up to [`Config::retransmit_count`] times. Each packet is reported with
+//!   [`Event::TxSuccess`] or [`Event::TxFailed`], and ACK payloads are reported as [`Event::Received`].
+//! - As PRX, packets received on the enabled pipes are reported as [`Event::Received`] and
+//!   acknowledged if requested. Packets in the TX queue are sent as ACK payloads to the next packet
+//!   received on their pipe, in order.
+//!
+//! Events are never dropped: while the event queue is full, no packet is transmitted as PTX, and
+//! received packets aren't acknowledged as PRX, so the transmitter sends them again later.
+//!
+//! Pipe 0 uses [`Config::base_address0`] and pipes 1 to 7 use [`Config::base_address1`], each with its
+//! own address prefix, forming 5-byte addresses.
+//!
+//! ```ignore
+//! static QUEUES: StaticCell<esb::Queues> = StaticCell::new();
+//! static RADIO: StaticCell<esb::EsbRadio> = StaticCell::new();
+//!
+//! let config = esb::Config::default();
+//! let (radio, mut esb) = esb::new(QUEUES.init(esb::Queues::new()), config).unwrap();
+//! let mut session = TimeslotSession::open(sd, RADIO.init(radio))?;
+//! join(esb::run(&mut session, &config), async {
+//!     esb.send(esb::Packet::new(0, b"hello").unwrap()).await;
+//!     let event = esb.next_event().await;
+//! })
+//! .await;
+//! ```
+
+use core::task::Poll;
+
+use embassy_sync::waitqueue::AtomicWaker;
+use futures::future::poll_fn;
+use heapless::spsc::{Consumer, Producer, Queue};
+
+use crate::radio::{Action, HfclkConfig, Priority, Request, Signal, SignalHandler, TimeslotSession};
+use crate::{pac, RawError};
+
+/// Maximum payload length of a packet.
+pub const MAX_PAYLOAD_LEN: usize = 32;
+
+const QUEUE_SIZE: usize = 8;
+
+/// Time kept at the end of each timeslot to stop the radio.
+const SLOT_END_MARGIN_US: u32 = 500;
+/// Bounds of [`Config::timeslot_length_us`]. The minimum leaves room for `SLOT_END_MARGIN_US`.
+const TIMESLOT_LENGTH_MIN_US: u32 = 1_000;
+const TIMESLOT_LENGTH_MAX_US: u32 = 100_000;
+/// Interval at which the TX queue is checked while idle as PTX.
+const TX_POLL_INTERVAL_US: u32 = 1000;
+/// Longest acceptable delay before the start of the first timeslot of a series.
+const EARLIEST_TIMEOUT_US: u32 = 100_000;
+
+const RADIO_SHORTS_READY_START: u32 = 1 << 0;
+const RADIO_SHORTS_END_DISABLE: u32 = 1 << 1;
+const RADIO_SHORTS_DISABLED_TXEN: u32 = 1 << 2;
+const RADIO_SHORTS_DISABLED_RXEN: u32 = 1 << 3;
+const RADIO_INT_DISABLED: u32 = 1 << 4;
+const RADIO_STATE_DISABLED: u32 = 0;
+
+const TIMER_INT_COMPARE0: u32 = 1 << 16;
+const TIMER_INT_COMPARE1: u32 = 1 << 17;
+
+/// Highest RF channel.
+const CHANNEL_MAX: u8 = 100;
+
+/// Role of the device.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Primary transmitter.
+    Ptx,
+    /// Primary receiver.
+    Prx,
+}
+
+/// On-air bitrate.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bitrate {
+    M1,
+    M2,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub mode: Mode,
+    pub bitrate: Bitrate,
+    /// RF channel, 0 to 100. The frequency is 2400 MHz + `channel` MHz.
+    pub channel: u8,
+    /// Transmit power in dBm, one of the values supported by the RADIO's TXPOWER register.
+    pub tx_power: i8,
+
+    /// Base address of pipe 0.
+    pub base_address0: [u8; 4],
+    /// Base address of pipes 1 to 7.
+    pub base_address1: [u8; 4],
+    /// Address prefix of each pipe.
+    pub prefixes: [u8; 8],
+    /// Bitmask of the pipes to receive on as PRX.
+    pub rx_pipes: u8,
+
+    /// Delay between retransmissions as PTX, in microseconds. It must be longer than the time
+    /// needed to receive an ACK with its payload.
+    pub retransmit_delay_us: u16,
+    /// Number of retransmissions as PTX before a packet is reported as failed, at most 254.
+    pub retransmit_count: u8,
+
+    /// Length of each timeslot, 1000 to 100_000 us.
+    pub timeslot_length_us: u32,
+    /// Distance between the start of consecutive timeslots, in microseconds. It must be at least
+    /// `timeslot_length_us`.
+    pub timeslot_period_us: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Ptx,
+            bitrate: Bitrate::M2,
+            channel: 2,
+            tx_power: 0,
+            base_address0: [0xE7, 0xE7, 0xE7, 0xE7],
+            base_address1: [0xC2, 0xC2, 0xC2, 0xC2],
+            prefixes: [0xE7, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8],
+            rx_pipes: 0xFF,
+            retransmit_delay_us: 250,
+            retransmit_count: 3,
+            timeslot_length_us: 10_000,
+            timeslot_period_us: 20_000,
+        }
+    }
+}
+
+/// Error returned by [`new`] for an invalid [`Config`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigError {
+    /// [`Config::channel`] is above 100.
+    InvalidChannel,
+    /// [`Config::retransmit_count`] is 255.
+    InvalidRetransmitCount,
+    /// [`Config::timeslot_length_us`] is outside 1000 to 100_000 us.
+    InvalidTimeslotLength,
+    /// [`Config::timeslot_period_us`] is shorter than [`Config::timeslot_length_us`].
+    InvalidTimeslotPeriod,
+}
+
+impl Config {
+    fn slot_request(&self) -> Request {
+        Request::Normal {
+            hfclk: HfclkConfig::XtalGuaranteed,
+            priority: Priority::Normal,
+            distance_us: self.timeslot_period_us,
+            length_us: self.timeslot_length_us,
+        }
+    }
+
+    fn first_slot_request(&self) -> Request {
+        Request::Earliest {
+            hfclk: HfclkConfig::XtalGuaranteed,
+            priority: Priority::Normal,
+            length_us: self.timeslot_length_us,
+            timeout_us: EARLIEST_TIMEOUT_US,
+        }
+    }
+
+    fn ack_timeout_us(&self) -> u32 {
+        match self.bitrate {
+            Bitrate::M1 => 400,
+            Bitrate::M2 => 300,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Packet {
+    pipe: u8,
+    len: u8,
+    no_ack: bool,
+    data: [u8; MAX_PAYLOAD_LEN],
+}
+
+impl Packet {
+    /// Create a packet for `pipe`, requesting an ACK.
+    ///
+    /// Returns `None` if `pipe` is above 7 or `data` is longer than [`MAX_PAYLOAD_LEN`].
+    pub fn new(pipe: u8, data: &[u8]) -> Option<Self> {
+        if pipe > 7 || data.len() > MAX_PAYLOAD_LEN {
+            return None;
+        }
+        let mut packet = Self {
+            pipe,
+            len: data.len() as u8,
+            no_ack: false,
+            data: [0; MAX_PAYLOAD_LEN],
+        };
+        packet.data[..data.len()].copy_from_slice(data);
+        Some(packet)
+    }
+
+    /// Don't request an ACK for this packet. It is reported as sent as soon as it's transmitted once.
+    pub fn without_ack(mut self) -> Self {
+        self.no_ack = true;
+        self
+    }
+
+    pub fn pipe(&self) -> u8 {
+        self.pipe
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..usize::from(self.len)]
+    }
+
+    /// Whether the sender requested an ACK.
+    pub fn ack_requested(&self) -> bool {
+        !self.no_ack
+    }
+
+    fn from_buf(pipe: u8, buf: &PacketBuf) -> Self {
+        let len = buf[0].min(MAX_PAYLOAD_LEN as u8);
+        let mut packet = Self {
+            pipe,
+            len,
+            no_ack: buf[1] & 0x01 == 0,
+            data: [0; MAX_PAYLOAD_LEN],
+        };
+        packet.data[..usize::from(len)].copy_from_slice(&buf[2..][..usize::from(len)]);
+        packet
+    }
+
+    fn write_to(&self, pid: u8, buf: &mut PacketBuf) {
+        buf[0] = self.len;
+        buf[1] = (pid << 1) | u8::from(!self.no_ack);
+        buf[2..][..usize::from(self.len)].copy_from_slice(self.data());
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The packet at the head of the TX queue was acknowledged, or sent once if it didn't request an ACK.
+    TxSuccess,
+    /// The packet at the head of the TX queue wasn't acknowledged after all retransmissions.
+    TxFailed,
+    /// A packet, or an ACK payload as PTX, was received.
+    Received(Packet),
+}
+
+/// Storage for the queues between the application and the radio.
+pub struct Queues {
+    tx: Queue<Packet, QUEUE_SIZE>,
+    events: Queue<Event, QUEUE_SIZE>,
+}
+
+impl Queues {
+    pub const fn new() -> Self {
+        Self {
+            tx: Queue::new(),
+            events: Queue::new(),
+        }
+    }
+}
+
+impl Default for Queues {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+pub(crate) fn on_swi() {
+    WAKER.wake();
+}
+
+/// Create the two halves of an ESB instance: the [`EsbRadio`] signal handler, to open a
+/// [`TimeslotSession`] with, and the [`Esb`] handle used by the application.
+///
+/// Only one instance may run at a time.
+pub fn new(queues: &'static mut Queues, config: Config) -> Result<(EsbRadio, Esb), ConfigError> {
+    if config.channel > CHANNEL_MAX {
+        return Err(ConfigError::InvalidChannel);
+    }
+    // The attempt counter must be able to go past the retransmit count.
+    if config.retransmit_count == u8::MAX {
+        return Err(ConfigError::InvalidRetransmitCount);
+    }
+    if !(TIMESLOT_LENGTH_MIN_US..=TIMESLOT_LENGTH_MAX_US).contains(&config.timeslot_length_us) {
+        return Err(ConfigError::InvalidTimeslotLength);
+    }
+    if config.timeslot_period_us < config.timeslot_length_us {
+        return Err(ConfigError::InvalidTimeslotPeriod);
+    }
+
+    let (tx_producer, tx_consumer) = queues.tx.split();
+    let (event_producer, event_consumer) = queues.events.split();
+    let radio = EsbRadio {
+        config,
+        tx: tx_consumer,
+        events: event_producer,
+        state: State::Idle,
+        pid: 0,
+        attempts: 0,
+        last_received: [None; 8],
+        last_ack: [None; 8],
+        tx_buf: [0; PACKET_BUF_LEN],
+        rx_buf: [0; PACKET_BUF_LEN],
+    };
+    let esb = Esb {
+        tx: tx_producer,
+        events: event_consumer,
+    };
+    Ok((radio, esb))
+}
+
+/// Keep requesting timeslots for ESB on the session.
+///
+/// Timeslots are chained every [`Config::timeslot_period_us`]. When one can't be scheduled, the
+/// session goes idle and a new series is requested. This only returns if a request fails.
+pub async fn run(session: &mut TimeslotSession, config: &Config) -> RawError {
+    loop {
+        if let Err(err) = session.request(config.first_slot_request()) {
+            return err;
+        }
+        session.wait_idle().await;
+    }
+}
+
+/// Application side of ESB.
+pub struct Esb {
+    tx: Producer<'static, Packet, QUEUE_SIZE>,
+    events: Consumer<'static, Event, QUEUE_SIZE>,
+}
+
+impl Esb {
+    /// Queue a packet for transmission, or as an ACK payload as PRX. Returns the packet if the queue is full.
+    pub fn try_send(&mut self, packet: Packet) -> Result<(), Packet> {
+        self.tx.enqueue(packet)
+    }
+
+    /// Queue a packet for transmission, or as an ACK payload as PRX, waiting for room in the queue.
+    pub async fn send(&mut self, packet: Packet) {
+        let mut packet = Some(packet);
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            match self.tx.enqueue(unwrap!(packet.take())) {
+                Ok(()) => Poll::Ready(()),
+                Err(p) => {
+                    packet = Some(p);
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    pub fn try_next_event(&mut self) -> Option<Event> {
+        self.events.dequeue()
+    }
+
+    pub async fn next_event(&mut self) -> Event {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            match self.events.dequeue() {
+                Some(event) => Poll::Ready(event),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+const PACKET_BUF_LEN: usize = 2 + MAX_PAYLOAD_LEN;
+type PacketBuf = [u8; PACKET_BUF_LEN];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum State {
+    Idle,
+    PtxTx,
+    PtxRxAck,
+    PtxRetransmitWait,
+    PrxRx,
+    PrxTxAck,
+}
+
+/// Timeslot signal handler driving the radio, see [`new`].
+pub struct EsbRadio {
+    config: Config,
+    tx: Consumer<'static, Packet, QUEUE_SIZE>,
+    events: Producer<'static, Event, QUEUE_SIZE>,
+    state: State,
+
+    // PTX: PID of the packet being sent, and transmissions of it so far.
+    pid: u8,
+    attempts: u8,
+    // PRX: PID and CRC of the last packet received on each pipe, to drop retransmissions, and the
+    // ACK payload sent for it, to send it again with the ACKs of the retransmissions.
+    last_received: [Option<(u8, u16)>; 8],
+    last_ack: [Option<Packet>; 8],
+
+    // The RADIO reads and writes these by DMA, the handler is never moved while a session is open.
+    tx_buf: PacketBuf,
+    rx_buf: PacketBuf,
+}
+
+fn radio() -> &'static pac::radio::RegisterBlock {
+    unsafe { &*pac::RADIO::ptr() }
+}
+
+fn timer() -> &'static pac::timer0::RegisterBlock {
+    unsafe { &*pac::TIMER0::ptr() }
+}
+
+fn timer_now() -> u32 {
+    let t = timer();
+    t.tasks_capture[3].write(|w| unsafe { w.bits(1) });
+    t.cc[3].read().bits()
+}
+
+fn timer_set_compare1(delay_us: u32) {
+    let t = timer();
+    t.events_compare[1].write(|w| unsafe { w.bits(0) });
+    t.cc[1].write(|w| unsafe { w.bits(timer_now().wrapping_add(delay_us)) });
+    t.intenset.write(|w| unsafe { w.bits(TIMER_INT_COMPARE1) });
+}
+
+fn timer_clear_compare1() {
+    let t = timer();
+    t.intenclr.write(|w| unsafe { w.bits(TIMER_INT_COMPARE1) });
+    t.events_compare[1].write(|w| unsafe { w.bits(0) });
+}
+
+/// Stop the radio, waiting until it's disabled.
+fn radio_disable() {
+    let r = radio();
+    r.shorts.write(|w| unsafe { w.bits(0) });
+    if r.state.read().bits() != RADIO_STATE_DISABLED {
+        r.events_disabled.write(|w| unsafe { w.bits(0) });
+        r.tasks_disable.write(|w| unsafe { w.bits(1) });
+        while r.events_disabled.read().bits() == 0 {}
+    }
+    r.events_disabled.write(|w| unsafe { w.bits(0) });
+}
+
+/// Convert an address in nRF24 bit order to the RADIO's.
+fn base_address(addr: [u8; 4]) -> u32 {
+    u32::from_be_bytes(addr.map(u8::reverse_bits))
+}
+
+fn prefixes(prefixes: &[u8]) -> u32 {
+    u32::from_le_bytes([prefixes[0], prefixes[1], prefixes[2], prefixes[3]].map(u8::reverse_bits))
+}
+
+impl EsbRadio {
+    /// Number of events that can be pushed without the queue overflowing.
+    fn events_room(&self) -> usize {
+        self.events.capacity() - self.events.len()
+    }
+
+    fn push_event(&mut self, event: Event) {
+        // Callers check there's room, see `events_room`.
+        let _ = self.events.enqueue(event);
+        crate::events::pend_swi();
+    }
+
+    fn configure(&self) {
+        let c = &self.config;
+        let r = radio();
+
+        r.mode.write(|w| unsafe {
+            w.bits(match c.bitrate {
+                Bitrate::M1 => 0,
+                Bitrate::M2 => 1,
+            })
+        });
+        // Fast ramp-up
+        r.modecnf0.write(|w| unsafe { w.bits(1) });
+        r.txpower.write(|w| unsafe { w.bits(u32::from(c.tx_power as u8)) });
+        r.frequency.write(|w| unsafe { w.bits(u32::from(c.channel)) });
+
+        // 6-bit length field, 3-bit S1 field holding the PID and the ACK request bit.
+        r.pcnf0.write(|w| unsafe { w.bits(6 | (3 << 16)) });
+        // Maximum length, 4-byte base address, big endian.
+        r.pcnf1
+            .write(|w| unsafe { w.bits(MAX_PAYLOAD_LEN as u32 | (4 << 16) | (1 << 24)) });
+        r.base0.write(|w| unsafe { w.bits(base_address(c.base_address0)) });
+        r.base1.write(|w| unsafe { w.bits(base_address(c.base_address1)) });
+        r.prefix0.write(|w| unsafe { w.bits(prefixes(&c.prefixes[..4])) });
+        r.prefix1.write(|w| unsafe { w.bits(prefixes(&c.prefixes[4..])) });
+
+        // 16-bit CRC over the address and payload.
+        r.crccnf.write(|w| unsafe { w.bits(2) });
+        r.crcinit.write(|w| unsafe { w.bits(0xFFFF) });
+        r.crcpoly.write(|w| unsafe { w.bits(0x11021) });
+
+        r.events_disabled.write(|w| unsafe { w.bits(0) });
+        r.intenset.write(|w| unsafe { w.bits(RADIO_INT_DISABLED) });
+
+        unsafe {
+            pac::NVIC::unmask(pac::interrupt::RADIO);
+            pac::NVIC::unmask(pac::interrupt::TIMER0);
+        }
+    }
+
+    fn on_start(&mut self) -> Action {
+        self.configure();
+
+        let t = timer();
+        t.events_compare[0].write(|w| unsafe { w.bits(0) });
+        t.events_compare[1].write(|w| unsafe { w.bits(0) });
+        t.cc[0].write(|w| unsafe { w.bits(self.config.timeslot_length_us.saturating_sub(SLOT_END_MARGIN_US)) });
+        t.intenset.write(|w| unsafe { w.bits(TIMER_INT_COMPARE0) });
+
+        match self.config.mode {
+            Mode::Ptx => self.ptx_start_tx(),
+            Mode::Prx => self.prx_start_rx(),
+        }
+        Action::None
+    }
+
+    fn on_slot_end(&mut self) -> Action {
+        let t = timer();
+        t.intenclr
+            .write(|w| unsafe { w.bits(TIMER_INT_COMPARE0 | TIMER_INT_COMPARE1) });
+        t.events_compare[0].write(|w| unsafe { w.bits(0) });
+        t.events_compare[1].write(|w| unsafe { w.bits(0) });
+
+        radio().intenclr.write(|w| unsafe { w.bits(RADIO_INT_DISABLED) });
+        radio_disable();
+
+        // An interrupted transmission is sent again in the next timeslot, without counting it as an attempt.
+        if matches!(self.state, State::PtxTx | State::PtxRxAck) {
+            self.attempts = self.attempts.saturating_sub(1);
+        }
+        self.state = State::Idle;
+
+        Action::RequestAndEnd(self.config.slot_request())
+    }
+
+    fn on_timer(&mut self) -> Action {
+        let t = timer();
+        if t.events_compare[0].read().bits() != 0 {
+            return self.on_slot_end();
+        }
+        if t.events_compare[1].read().bits() != 0 {
+            timer_clear_compare1();
+            match self.state {
+                State::PtxRxAck => {
+                    radio_disable();
+                    self.ptx_retransmit();
+                }
+                State::PtxRetransmitWait | State::Idle if self.config.mode == Mode::Ptx => self.ptx_start_tx(),
+                _ => {}
+            }
+        }
+        Action::None
+    }
+
+    fn on_radio(&mut self) -> Action {
+        let r = radio();
+        if r.events_disabled.read().bits() == 0 {
+            return Action::None;
+        }
+        r.events_disabled.write(|w| unsafe { w.bits(0) });
+
+        match self.state {
+            State::PtxTx => self.ptx_on_tx_done(),
+            State::PtxRxAck => self.ptx_on_rx_done(),
+            State::PrxRx => self.prx_on_rx_done(),
+            State::PrxTxAck => {
+                // The radio is switching back to RX with the DISABLED_RXEN short.
+                r.packetptr
+                    .write(|w| unsafe { w.bits(self.rx_buf.as_mut_ptr() as u32) });
+                r.shorts.write(|w| unsafe {
+                    w.bits(RADIO_SHORTS_READY_START | RADIO_SHORTS_END_DISABLE | RADIO_SHORTS_DISABLED_TXEN)
+                });
+                self.state = State::PrxRx;
+            }
+            State::Idle | State::PtxRetransmitWait => {}
+        }
+        Action::None
+    }
+
+    fn ptx_start_tx(&mut self) {
+        // Completing the packet pushes up to two events, the TX result and the ACK payload.
+        let packet = match self.tx.peek() {
+            Some(packet) if self.events_room() >= 2 => *packet,
+            _ => {
+                self.state = State::Idle;
+                timer_set_compare1(TX_POLL_INTERVAL_US);
+                return;
+            }
+        };
+
+        packet.write_to(self.pid, &mut self.tx_buf);
+        self.attempts += 1;
+
+        let r = radio();
+        r.txaddress.write(|w| unsafe { w.bits(u32::from(packet.pipe)) });
+        r.rxaddresses.write(|w| unsafe { w.bits(1 << packet.pipe) });
+        r.packetptr.write(|w| unsafe { w.bits(self.tx_buf.as_ptr() as u32) });
+        let mut shorts = RADIO_SHORTS_READY_START | RADIO_SHORTS_END_DISABLE;
+        if !packet.no_ack {
+            shorts |= RADIO_SHORTS_DISABLED_RXEN;
+        }
+        r.shorts.write(|w| unsafe { w.bits(shorts) });
+        r.events_disabled.write(|w| unsafe { w.bits(0) });
+
+        self.state = State::PtxTx;
+        r.tasks_txen.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn ptx_complete(&mut self, event: Event) {
+        self.tx.dequeue();
+        self.push_event(event);
+        self.pid = (self.pid + 1) & 0x03;
+        self.attempts = 0;
+    }
+
+    fn ptx_on_tx_done(&mut self) {
+        if self.tx.peek().map_or(true, |p| p.no_ack) {
+            self.ptx_complete(Event::TxSuccess);
+            self.ptx_start_tx();
+            return;
+        }
+
+        // The radio is switching to RX with the DISABLED_RXEN short.
+        let r = radio();
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.rx_buf.as_mut_ptr() as u32) });
+        r.shorts
+            .write(|w| unsafe { w.bits(RADIO_SHORTS_READY_START | RADIO_SHORTS_END_DISABLE) });
+        self.state = State::PtxRxAck;
+        timer_set_compare1(self.config.ack_timeout_us());
+    }
+
+    fn ptx_on_rx_done(&mut self) {
+        timer_clear_compare1();
+
+        let r = radio();
+        if r.crcstatus.read().bits() & 0x01 == 0 {
+            self.ptx_retransmit();
+            return;
+        }
+
+        let pipe = unwrap!(self.tx.peek()).pipe;
+        let ack_payload = (self.rx_buf[0] != 0).then(|| Packet::from_buf(pipe, &self.rx_buf));
+        self.ptx_complete(Event::TxSuccess);
+        if let Some(packet) = ack_payload {
+            self.push_event(Event::Received(packet));
+        }
+        self.ptx_start_tx();
+    }
+
+    fn ptx_retransmit(&mut self) {
+        if self.attempts > self.config.retransmit_count {
+            self.ptx_complete(Event::TxFailed);
+            self.ptx_start_tx();
+        } else {
+            self.state = State::PtxRetransmitWait;
+            timer_set_compare1(u32::from(self.config.retransmit_delay_us));
+        }
+    }
+
+    fn prx_start_rx(&mut self) {
+        let r = radio();
+        r.rxaddresses
+            .write(|w| unsafe { w.bits(u32::from(self.config.rx_pipes)) });
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.rx_buf.as_mut_ptr() as u32) });
+        r.shorts.write(|w| unsafe {
+            w.bits(RADIO_SHORTS_READY_START | RADIO_SHORTS_END_DISABLE | RADIO_SHORTS_DISABLED_TXEN)
+        });
+        r.events_disabled.write(|w| unsafe { w.bits(0) });
+
+        self.state = State::PrxRx;
+        r.tasks_rxen.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn prx_on_rx_done(&mut self) {
+        let r = radio();
+        let crc_ok = r.crcstatus.read().bits() & 0x01 != 0;
+        let pipe = r.rxmatch.read().bits() as u8 & 0x07;
+        let crc = r.rxcrc.read().bits() as u16;
+        let pid = (self.rx_buf[1] >> 1) & 0x03;
+        let packet = Packet::from_buf(pipe, &self.rx_buf);
+
+        let duplicate = self.last_received[usize::from(pipe)] == Some((pid, crc));
+        // Without room to report a new packet, it isn't acknowledged so the transmitter sends it again.
+        let accepted = crc_ok && (duplicate || self.events_room() >= 1);
+
+        if !accepted || packet.no_ack {
+            // Cancel the ACK transmission started by the DISABLED_TXEN short.
+            radio_disable();
+        } else {
+            // Send the ACK. A retransmission gets the same payload as the original, otherwise the next
+            // packet queued for this pipe is sent.
+            r.txaddress.write(|w| unsafe { w.bits(u32::from(pipe)) });
+            if !duplicate {
+                self.last_ack[usize::from(pipe)] = match self.tx.peek() {
+                    Some(p) if p.pipe == pipe => {
+                        crate::events::pend_swi();
+                        self.tx.dequeue()
+                    }
+                    _ => None,
+                };
+            }
+            match self.last_ack[usize::from(pipe)] {
+                Some(p) => p.write_to(pid, &mut self.tx_buf),
+                None => {
+                    self.tx_buf[0] = 0;
+                    self.tx_buf[1] = pid << 1;
+                }
+            }
+            r.packetptr.write(|w| unsafe { w.bits(self.tx_buf.as_ptr() as u32) });
+            r.shorts.write(|w| unsafe {
+                w.bits(RADIO_SHORTS_READY_START | RADIO_SHORTS_END_DISABLE | RADIO_SHORTS_DISABLED_RXEN)
+            });
+            self.state = State::PrxTxAck;
+        }
+
+        if accepted && !duplicate {
+            self.last_received[usize::from(pipe)] = Some((pid, crc));
+            self.push_event(Event::Received(packet));
+        }
+
+        if self.state == State::PrxRx {
+            self.prx_start_rx();
+        }
+    }
+}
+
+impl SignalHandler for EsbRadio {
+    fn on_signal(&mut self, signal: Signal) -> Action {
+        match signal {
+            Signal::Start => self.on_start(),
+            Signal::Timer0 => self.on_timer(),
+            Signal::Radio => self.on_radio(),
+            Signal::ExtendFailed | Signal::ExtendSucceeded => Action::None,
+        }
+    }
+}
@@ -44,6 +44,10 @@ fn on_soc_evt<F: FnMut(SocEvent)>(evt: u32, evt_handler: &mut F) {
                 Err(_) => panic!("Unknown soc evt {:?}", evt),
             };
 
+            crate::clock::on_soc_evt(evt);
+            crate::radio::on_soc_evt(evt);
+            #[cfg(any(feature = "s113", feature = "s122", feature = "s140"))]
+            crate::usb_supply::on_soc_evt(evt);
             evt_handler(evt)
         }
     }
@@ -105,6 +109,7 @@ pub(crate) async fn run_ble() -> ! {
 unsafe fn SWI2() {
     SWI2_SOC_EVT_WAKER.wake();
     SWI2_BLE_EVT_WAKER.wake();
+    crate::radio::on_swi();
 }
 
 #[cfg(not(any(feature = "nrf52805", feature = "nrf52810", feature = "nrf52811")))]
@@ -112,4 +117,13 @@ unsafe fn SWI2() {
 unsafe fn SWI2_EGU2() {
     SWI2_SOC_EVT_WAKER.wake();
     SWI2_BLE_EVT_WAKER.wake();
+    crate::radio::on_swi();
+}
+
+/// Pend the softdevice event interrupt, to wake up tasks from contexts where the softdevice can't be called.
+pub(crate) fn pend_swi() {
+    #[cfg(any(feature = "nrf52805", feature = "nrf52810", feature = "nrf52811"))]
+    crate::pac::NVIC::pend(crate::pac::interrupt::SWI2);
+    #[cfg(not(any(feature = "nrf52805", feature = "nrf52810", feature = "nrf52811")))]
+    crate::pac::NVIC::pend(crate::pac::interrupt::SWI2_EGU2);
 }
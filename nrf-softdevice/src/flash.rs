@@ -45,7 +45,7 @@ impl Flash {
     ///
     /// # Panics
     ///
-    /// Panics if called more than once.
+    /// Panics if called while another Flash instance is alive.
     pub fn take(_sd: &Softdevice) -> Flash {
         if FLASH_TAKEN
             .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
@@ -58,6 +58,16 @@ impl Flash {
     }
 }
 
+impl Drop for Flash {
+    fn drop(&mut self) {
+        FLASH_TAKEN.store(false, Ordering::Release);
+    }
+}
+
+pub(crate) fn is_taken() -> bool {
+    FLASH_TAKEN.load(Ordering::Acquire)
+}
+
 static SIGNAL: Signal<CriticalSectionRawMutex, Result<(), FlashError>> = Signal::new();
 
 pub(crate) fn on_flash_success() {
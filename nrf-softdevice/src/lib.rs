@@ -160,8 +160,21 @@ pub use flash::*;
 mod raw_error;
 pub use raw_error::*;
 pub mod ble;
+pub mod clock;
+mod config;
+pub use config::*;
 mod softdevice;
 pub use softdevice::*;
+pub mod esb;
+pub mod power;
+pub mod ppi;
+pub mod radio;
+pub mod radio_notification;
+
+#[cfg(any(feature = "s113", feature = "s122", feature = "s140"))]
+mod usb_supply;
+#[cfg(any(feature = "s113", feature = "s122", feature = "s140"))]
+pub use usb_supply::UsbSupply;
 
 mod temperature;
 pub use temperature::temperature_celsius;
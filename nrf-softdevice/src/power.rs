@@ -0,0 +1,298 @@
+//! Power management through the softdevice.
+//!
+//! While the softdevice is enabled, the POWER peripheral is restricted and must be accessed through
+//! these functions instead.
+
+use core::ops::{BitOr, BitOrAssign};
+
+use crate::{raw, RawError, Softdevice};
+
+/// Power failure comparator threshold on VDD.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PofThreshold {
+    V1_7 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V17 as u8,
+    V1_8 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V18 as u8,
+    V1_9 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V19 as u8,
+    V2_0 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V20 as u8,
+    V2_1 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V21 as u8,
+    V2_2 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V22 as u8,
+    V2_3 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V23 as u8,
+    V2_4 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V24 as u8,
+    V2_5 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V25 as u8,
+    V2_6 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V26 as u8,
+    V2_7 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V27 as u8,
+    V2_8 = raw::NRF_POWER_THRESHOLDS_NRF_POWER_THRESHOLD_V28 as u8,
+}
+
+/// Power failure comparator threshold on VDDH, for chips in high voltage mode.
+#[cfg(feature = "s140")]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PofThresholdVddh {
+    V2_7 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V27 as u8,
+    V2_8 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V28 as u8,
+    V2_9 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V29 as u8,
+    V3_0 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V30 as u8,
+    V3_1 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V31 as u8,
+    V3_2 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V32 as u8,
+    V3_3 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V33 as u8,
+    V3_4 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V34 as u8,
+    V3_5 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V35 as u8,
+    V3_6 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V36 as u8,
+    V3_7 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V37 as u8,
+    V3_8 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V38 as u8,
+    V3_9 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V39 as u8,
+    V4_0 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V40 as u8,
+    V4_1 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V41 as u8,
+    V4_2 = raw::NRF_POWER_THRESHOLDVDDHS_NRF_POWER_THRESHOLDVDDH_V42 as u8,
+}
+
+/// Power mode used when the CPU sleeps.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PowerMode {
+    /// Constant latency, keeping some resources running for faster wakeup.
+    ConstantLatency = raw::NRF_POWER_MODES_NRF_POWER_MODE_CONSTLAT as u8,
+    /// Low power, the default.
+    LowPower = raw::NRF_POWER_MODES_NRF_POWER_MODE_LOWPWR as u8,
+}
+
+/// General purpose retention register, kept across resets other than power-on and brownout.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gpregret {
+    Gpregret,
+    Gpregret2,
+}
+
+/// Reasons for the last resets, from the RESETREAS register.
+///
+/// Reasons accumulate across resets until cleared with [`clear_reset_reason`]. If none is set, the
+/// reset was caused by the power-on or brownout reset.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResetReason(u32);
+
+impl ResetReason {
+    /// Reset pin.
+    pub const RESET_PIN: Self = Self(1 << 0);
+    /// Watchdog.
+    pub const WATCHDOG: Self = Self(1 << 1);
+    /// Soft reset, e.g. with `SCB::sys_reset`.
+    pub const SOFT_RESET: Self = Self(1 << 2);
+    /// CPU lockup.
+    pub const LOCKUP: Self = Self(1 << 3);
+    /// Wakeup from System OFF by a GPIO DETECT signal.
+    pub const OFF: Self = Self(1 << 16);
+    /// Wakeup from System OFF by LPCOMP.
+    pub const LPCOMP: Self = Self(1 << 17);
+    /// Wakeup from System OFF by the debug interface.
+    pub const DEBUG_INTERFACE: Self = Self(1 << 18);
+    /// Wakeup from System OFF by NFC field detection.
+    pub const NFC: Self = Self(1 << 19);
+    /// Wakeup from System OFF by VBUS rising.
+    pub const VBUS: Self = Self(1 << 20);
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all reasons in `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for ResetReason {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for ResetReason {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Enable the power failure comparator, raising [`SocEvent::PowerFailureWarning`](crate::SocEvent::PowerFailureWarning)
+/// when VDD drops below `threshold`.
+pub fn enable_pof(_sd: &Softdevice, threshold: PofThreshold) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_pof_threshold_set(threshold as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_pof_threshold_set err {:?}", err);
+        return Err(err);
+    }
+
+    let ret = unsafe { raw::sd_power_pof_enable(1) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_pof_enable err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Set the power failure comparator threshold on VDDH, used in high voltage mode.
+#[cfg(feature = "s140")]
+pub fn set_pof_threshold_vddh(_sd: &Softdevice, threshold: PofThresholdVddh) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_pof_thresholdvddh_set(threshold as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_pof_thresholdvddh_set err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Disable the power failure comparator.
+pub fn disable_pof(_sd: &Softdevice) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_pof_enable(0) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_pof_enable err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Enable or disable the DC/DC regulator. The DC/DC components must be present on the board.
+pub fn set_dcdc(_sd: &Softdevice, enable: bool) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_dcdc_mode_set(enable as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_dcdc_mode_set err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Enable or disable the DC/DC regulator of the high voltage stage (REG0).
+#[cfg(feature = "s140")]
+pub fn set_dcdc0(_sd: &Softdevice, enable: bool) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_dcdc0_mode_set(enable as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_dcdc0_mode_set err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Set the power mode used when the CPU sleeps.
+pub fn set_power_mode(_sd: &Softdevice, mode: PowerMode) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_mode_set(mode as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_mode_set err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub fn reset_reason(_sd: &Softdevice) -> Result<ResetReason, RawError> {
+    let mut reason: u32 = 0;
+    let ret = unsafe { raw::sd_power_reset_reason_get(&mut reason) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_reset_reason_get err {:?}", err);
+        return Err(err);
+    }
+    Ok(ResetReason(reason))
+}
+
+/// Clear the given reasons, so they're not reported again after the next reset.
+pub fn clear_reset_reason(_sd: &Softdevice, reason: ResetReason) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_reset_reason_clr(reason.0) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_reset_reason_clr err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn gpregret_id(reg: Gpregret) -> u32 {
+    match reg {
+        Gpregret::Gpregret => 0,
+        Gpregret::Gpregret2 => 1,
+    }
+}
+
+pub fn gpregret_get(_sd: &Softdevice, reg: Gpregret) -> Result<u8, RawError> {
+    let mut value: u32 = 0;
+    let ret = unsafe { raw::sd_power_gpregret_get(gpregret_id(reg), &mut value) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_gpregret_get err {:?}", err);
+        return Err(err);
+    }
+    Ok(value as u8)
+}
+
+/// Set the bits of `mask` in the register.
+pub fn gpregret_set(_sd: &Softdevice, reg: Gpregret, mask: u8) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_gpregret_set(gpregret_id(reg), u32::from(mask)) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_gpregret_set err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Clear the bits of `mask` in the register.
+pub fn gpregret_clear(_sd: &Softdevice, reg: Gpregret, mask: u8) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_gpregret_clr(gpregret_id(reg), u32::from(mask)) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_gpregret_clr err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Get the power status of the sections of RAM block `index`, from the `RAM[index].POWER` register.
+pub fn ram_power_get(_sd: &Softdevice, index: u8) -> Result<u32, RawError> {
+    let mut power: u32 = 0;
+    let ret = unsafe { raw::sd_power_ram_power_get(index, &mut power) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_ram_power_get err {:?}", err);
+        return Err(err);
+    }
+    Ok(power)
+}
+
+/// Power on, or retain in System OFF, the sections of RAM block `index` set in `mask`.
+pub fn ram_power_set(_sd: &Softdevice, index: u8, mask: u32) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_ram_power_set(index, mask) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_ram_power_set err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Power off, or stop retaining in System OFF, the sections of RAM block `index` set in `mask`.
+pub fn ram_power_clear(_sd: &Softdevice, index: u8, mask: u32) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_ram_power_clr(index, mask) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_ram_power_clr err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Enter System OFF. The chip wakes up through a reset, see [`ResetReason`].
+///
+/// When a debugger is attached, System OFF is emulated and this sleeps forever instead.
+pub fn system_off(_sd: &Softdevice) -> ! {
+    let _ret = unsafe { raw::sd_power_system_off() };
+    warn!("sd_power_system_off returned {:?}", _ret);
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
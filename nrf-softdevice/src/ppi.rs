@@ -0,0 +1,229 @@
+//! PPI channels and groups.
+//!
+//! While the softdevice is enabled, PPI must be configured through it, and some channels and groups
+//! are reserved for its own use. This hands out the channels and groups available to the application
+//! as owned handles, so several drivers can share PPI without conflicts. Handles are freed on drop.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{raw, RawError, Softdevice};
+
+/// Channels not reserved by the softdevice, 0 to 16.
+const APP_CHANNELS: u32 = 0x0001_FFFF;
+/// Groups not reserved by the softdevice, 0 to 3.
+const APP_GROUPS: u32 = 0x0F;
+
+static CHANNELS_TAKEN: AtomicU32 = AtomicU32::new(0);
+static GROUPS_TAKEN: AtomicU32 = AtomicU32::new(0);
+
+/// Take the lowest free bit of `available` in `taken`.
+fn alloc(taken: &AtomicU32, available: u32) -> Option<u8> {
+    let mut current = taken.load(Ordering::Acquire);
+    loop {
+        let free = available & !current;
+        if free == 0 {
+            return None;
+        }
+        let n = free.trailing_zeros() as u8;
+        match taken.compare_exchange_weak(current, current | (1 << n), Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return Some(n),
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+fn free(taken: &AtomicU32, n: u8) {
+    taken.fetch_and(!(1 << n), Ordering::AcqRel);
+}
+
+pub(crate) fn any_allocated() -> bool {
+    CHANNELS_TAKEN.load(Ordering::Acquire) != 0 || GROUPS_TAKEN.load(Ordering::Acquire) != 0
+}
+
+/// Event endpoint of a PPI channel.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Event(u32);
+
+impl Event {
+    /// Create an event endpoint from the address of a peripheral's `EVENTS_*` register.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the address of an event register.
+    pub const unsafe fn from_address(address: u32) -> Self {
+        Self(address)
+    }
+
+    pub const fn address(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Task endpoint of a PPI channel.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Task(u32);
+
+impl Task {
+    /// Create a task endpoint from the address of a peripheral's `TASKS_*` register.
+    ///
+    /// # Safety
+    ///
+    /// `address` must be the address of a task register, and triggering the task must not break
+    /// any invariant of the code owning the peripheral.
+    pub const unsafe fn from_address(address: u32) -> Self {
+        Self(address)
+    }
+
+    pub const fn address(&self) -> u32 {
+        self.0
+    }
+}
+
+/// A PPI channel owned by the application.
+pub struct Channel {
+    number: u8,
+}
+
+impl Channel {
+    /// Allocate a free channel, or `None` if all the channels available to the application are taken.
+    pub fn alloc(_sd: &Softdevice) -> Option<Channel> {
+        alloc(&CHANNELS_TAKEN, APP_CHANNELS).map(|number| Channel { number })
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// Connect `event` to `task`. The channel must be enabled for the event to trigger the task.
+    pub fn connect(&mut self, event: Event, task: Task) -> Result<(), RawError> {
+        let ret = unsafe { raw::sd_ppi_channel_assign(self.number, event.0 as *const _, task.0 as *const _) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_channel_assign err {:?}", err);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub fn enable(&mut self) -> Result<(), RawError> {
+        let ret = unsafe { raw::sd_ppi_channel_enable_set(1 << self.number) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_channel_enable_set err {:?}", err);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub fn disable(&mut self) -> Result<(), RawError> {
+        let ret = unsafe { raw::sd_ppi_channel_enable_clr(1 << self.number) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_channel_enable_clr err {:?}", err);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> Result<bool, RawError> {
+        let mut enabled: u32 = 0;
+        let ret = unsafe { raw::sd_ppi_channel_enable_get(&mut enabled) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_channel_enable_get err {:?}", err);
+            return Err(err);
+        }
+        Ok(enabled & (1 << self.number) != 0)
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        let _ = self.disable();
+        remove_from_groups(self.number);
+        free(&CHANNELS_TAKEN, self.number);
+    }
+}
+
+/// Remove a channel from the application's groups, so enabling a group doesn't enable the channel
+/// once it's allocated again.
+fn remove_from_groups(channel: u8) {
+    let groups = GROUPS_TAKEN.load(Ordering::Acquire);
+    for group in 0..u32::BITS as u8 {
+        if groups & (1 << group) == 0 {
+            continue;
+        }
+        let mut mask: u32 = 0;
+        let ret = unsafe { raw::sd_ppi_group_get(group, &mut mask) };
+        if RawError::convert(ret).is_ok() && mask & (1 << channel) != 0 {
+            let ret = unsafe { raw::sd_ppi_group_assign(group, mask & !(1 << channel)) };
+            if let Err(_err) = RawError::convert(ret) {
+                warn!("sd_ppi_group_assign err {:?}", _err);
+            }
+        }
+    }
+}
+
+/// A PPI channel group owned by the application, to enable or disable several channels at once.
+pub struct Group {
+    number: u8,
+}
+
+impl Group {
+    /// Allocate a free group, or `None` if all the groups available to the application are taken.
+    pub fn alloc(_sd: &Softdevice) -> Option<Group> {
+        alloc(&GROUPS_TAKEN, APP_GROUPS).map(|number| Group { number })
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// Set the channels of the group, replacing the previous ones.
+    pub fn assign(&mut self, channels: &[&Channel]) -> Result<(), RawError> {
+        let mask = channels.iter().fold(0u32, |mask, ch| mask | (1 << ch.number));
+        let ret = unsafe { raw::sd_ppi_group_assign(self.number, mask) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_group_assign err {:?}", err);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Bitmask of the channels in the group.
+    pub fn channels(&self) -> Result<u32, RawError> {
+        let mut mask: u32 = 0;
+        let ret = unsafe { raw::sd_ppi_group_get(self.number, &mut mask) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_group_get err {:?}", err);
+            return Err(err);
+        }
+        Ok(mask)
+    }
+
+    /// Enable all the channels of the group.
+    pub fn enable(&mut self) -> Result<(), RawError> {
+        let ret = unsafe { raw::sd_ppi_group_task_enable(self.number) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_group_task_enable err {:?}", err);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Disable all the channels of the group.
+    pub fn disable(&mut self) -> Result<(), RawError> {
+        let ret = unsafe { raw::sd_ppi_group_task_disable(self.number) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_ppi_group_task_disable err {:?}", err);
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        // Leave the member channels as they are, only forget them.
+        let _ = self.assign(&[]);
+        free(&GROUPS_TAKEN, self.number);
+    }
+}
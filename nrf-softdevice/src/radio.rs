@@ -0,0 +1,435 @@
+//! Radio timeslot API.
+//!
+//! A [`TimeslotSession`] gets exclusive access to the RADIO and TIMER0 peripherals during timeslots
+//! scheduled by the softdevice between its own radio activity, for example to run a proprietary
+//! 2.4 GHz protocol alongside BLE.
+//!
+//! During a timeslot, the softdevice calls the session's [`SignalHandler`] at the highest interrupt
+//! priority for the start of the timeslot and for RADIO and TIMER0 interrupts. The handler decides
+//! whether to extend, end, or end and request the next timeslot. As no `sd_*` function can be called
+//! from there, the handler should only communicate with the rest of the application through atomics,
+//! and the start and end of timeslots can be awaited on the session.
+//!
+//! [`Softdevice::run`](crate::Softdevice::run) must be running to get notified of blocked and canceled requests.
+
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::task::Poll;
+
+use embassy_sync::waitqueue::AtomicWaker;
+use futures::future::poll_fn;
+
+use crate::{raw, RawError, SocEvent, Softdevice};
+
+/// High frequency clock source during a timeslot.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum HfclkConfig {
+    /// The external crystal runs during the whole timeslot. Use this when using the radio.
+    XtalGuaranteed = raw::NRF_RADIO_HFCLK_CFG_NRF_RADIO_HFCLK_CFG_XTAL_GUARANTEED as u8,
+    /// The RC oscillator may be used, allowing earlier and tighter scheduling. The crystal must
+    /// be started and stable before using the radio.
+    NoGuarantee = raw::NRF_RADIO_HFCLK_CFG_NRF_RADIO_HFCLK_CFG_NO_GUARANTEE as u8,
+}
+
+/// Priority of a timeslot relative to the softdevice's radio activity.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Priority {
+    /// Same priority as the softdevice's connections.
+    High = raw::NRF_RADIO_PRIORITY_NRF_RADIO_PRIORITY_HIGH as u8,
+    /// Same priority as the softdevice's secondary activities.
+    Normal = raw::NRF_RADIO_PRIORITY_NRF_RADIO_PRIORITY_NORMAL as u8,
+}
+
+/// Timeslot request.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Request {
+    /// Timeslot as early as possible. The first request of a session must be of this type.
+    Earliest {
+        hfclk: HfclkConfig,
+        priority: Priority,
+        /// Timeslot length, 100 to 100_000 us.
+        length_us: u32,
+        /// Longest acceptable delay until the start of the timeslot, up to 127_999_999 us.
+        timeout_us: u32,
+    },
+    /// Timeslot starting at a given distance from the start of the previous one.
+    Normal {
+        hfclk: HfclkConfig,
+        priority: Priority,
+        /// Distance from the start of the previous timeslot, up to 127_999_999 us.
+        distance_us: u32,
+        /// Timeslot length, 100 to 100_000 us.
+        length_us: u32,
+    },
+}
+
+impl Request {
+    fn to_raw(self) -> raw::nrf_radio_request_t {
+        match self {
+            Request::Earliest {
+                hfclk,
+                priority,
+                length_us,
+                timeout_us,
+            } => raw::nrf_radio_request_t {
+                request_type: raw::NRF_RADIO_REQUEST_TYPE_NRF_RADIO_REQ_TYPE_EARLIEST as u8,
+                params: raw::nrf_radio_request_t__bindgen_ty_1 {
+                    earliest: raw::nrf_radio_request_earliest_t {
+                        hfclk: hfclk as u8,
+                        priority: priority as u8,
+                        length_us,
+                        timeout_us,
+                    },
+                },
+            },
+            Request::Normal {
+                hfclk,
+                priority,
+                distance_us,
+                length_us,
+            } => raw::nrf_radio_request_t {
+                request_type: raw::NRF_RADIO_REQUEST_TYPE_NRF_RADIO_REQ_TYPE_NORMAL as u8,
+                params: raw::nrf_radio_request_t__bindgen_ty_1 {
+                    normal: raw::nrf_radio_request_normal_t {
+                        hfclk: hfclk as u8,
+                        priority: priority as u8,
+                        distance_us,
+                        length_us,
+                    },
+                },
+            },
+        }
+    }
+}
+
+/// Signal from the softdevice during a timeslot.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Signal {
+    /// The timeslot started. TIMER0 has been reset and runs at 1 MHz.
+    Start,
+    /// TIMER0 interrupt.
+    Timer0,
+    /// RADIO interrupt.
+    Radio,
+    /// The last [`Action::Extend`] failed.
+    ExtendFailed,
+    /// The last [`Action::Extend`] succeeded.
+    ExtendSucceeded,
+}
+
+impl Signal {
+    fn from_raw(signal_type: u8) -> Option<Self> {
+        match u32::from(signal_type) {
+            raw::NRF_RADIO_CALLBACK_SIGNAL_TYPE_NRF_RADIO_CALLBACK_SIGNAL_TYPE_START => Some(Self::Start),
+            raw::NRF_RADIO_CALLBACK_SIGNAL_TYPE_NRF_RADIO_CALLBACK_SIGNAL_TYPE_TIMER0 => Some(Self::Timer0),
+            raw::NRF_RADIO_CALLBACK_SIGNAL_TYPE_NRF_RADIO_CALLBACK_SIGNAL_TYPE_RADIO => Some(Self::Radio),
+            raw::NRF_RADIO_CALLBACK_SIGNAL_TYPE_NRF_RADIO_CALLBACK_SIGNAL_TYPE_EXTEND_FAILED => {
+                Some(Self::ExtendFailed)
+            }
+            raw::NRF_RADIO_CALLBACK_SIGNAL_TYPE_NRF_RADIO_CALLBACK_SIGNAL_TYPE_EXTEND_SUCCEEDED => {
+                Some(Self::ExtendSucceeded)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Action to take after handling a [`Signal`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Action {
+    /// Continue the timeslot.
+    None,
+    /// Extend the timeslot by `length_us`, at least 200 us. This must be returned at least 82 us before
+    /// the end of the timeslot. The result is reported with [`Signal::ExtendSucceeded`] or [`Signal::ExtendFailed`].
+    Extend { length_us: u32 },
+    /// End the timeslot.
+    End,
+    /// End the timeslot and request the next one.
+    RequestAndEnd(Request),
+}
+
+/// Handler for the signals of a [`TimeslotSession`].
+pub trait SignalHandler {
+    /// Handle a signal. This runs at the highest interrupt priority, during the timeslot.
+    ///
+    /// The handler must end the timeslot before its end, RADIO and TIMER0 must not be used after that.
+    fn on_signal(&mut self, signal: Signal) -> Action;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimeslotError {
+    /// The requested timeslot couldn't be scheduled.
+    Blocked,
+    /// The requested timeslot was canceled by higher priority radio activity.
+    Canceled,
+    /// The signal handler returned an action the softdevice rejected.
+    InvalidReturn,
+    /// The session was closed.
+    Closed,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OpenError {
+    /// Another session is open, or still closing.
+    Busy,
+    Raw(RawError),
+}
+
+impl From<RawError> for OpenError {
+    fn from(err: RawError) -> Self {
+        OpenError::Raw(err)
+    }
+}
+
+const FLAG_BLOCKED: u32 = 1 << 0;
+const FLAG_CANCELED: u32 = 1 << 1;
+const FLAG_INVALID_RETURN: u32 = 1 << 2;
+const FLAG_IDLE: u32 = 1 << 3;
+const FLAG_CLOSED: u32 = 1 << 4;
+
+static SESSION_OPEN: AtomicBool = AtomicBool::new(false);
+static STARTED: AtomicU32 = AtomicU32::new(0);
+static ENDED: AtomicU32 = AtomicU32::new(0);
+static FLAGS: AtomicU32 = AtomicU32::new(0);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+// Only accessed from the signal callback while a session is open, or before opening one.
+static mut HANDLER: Option<NonNull<dyn SignalHandler + Send>> = None;
+// The softdevice reads these after the signal callback returns.
+static mut RETURN_PARAM: raw::nrf_radio_signal_callback_return_param_t =
+    raw::nrf_radio_signal_callback_return_param_t {
+        callback_action: 0,
+        params: raw::nrf_radio_signal_callback_return_param_t__bindgen_ty_1 {
+            extend: raw::nrf_radio_signal_callback_return_param_t__bindgen_ty_1__bindgen_ty_2 { length_us: 0 },
+        },
+    };
+static mut NEXT_REQUEST: raw::nrf_radio_request_t = raw::nrf_radio_request_t {
+    request_type: 0,
+    params: raw::nrf_radio_request_t__bindgen_ty_1 {
+        earliest: raw::nrf_radio_request_earliest_t {
+            hfclk: 0,
+            priority: 0,
+            length_us: 0,
+            timeout_us: 0,
+        },
+    },
+};
+
+unsafe extern "C" fn signal_callback(signal_type: u8) -> *mut raw::nrf_radio_signal_callback_return_param_t {
+    let ret = &mut *ptr::addr_of_mut!(RETURN_PARAM);
+    ret.callback_action = raw::NRF_RADIO_SIGNAL_CALLBACK_ACTION_NRF_RADIO_SIGNAL_CALLBACK_ACTION_NONE as u8;
+
+    let (Some(signal), Some(mut handler)) = (Signal::from_raw(signal_type), HANDLER) else {
+        return ret;
+    };
+
+    if signal == Signal::Start {
+        STARTED.fetch_add(1, Ordering::Release);
+        crate::events::pend_swi();
+    }
+
+    match handler.as_mut().on_signal(signal) {
+        Action::None => {}
+        Action::Extend { length_us } => {
+            ret.callback_action = raw::NRF_RADIO_SIGNAL_CALLBACK_ACTION_NRF_RADIO_SIGNAL_CALLBACK_ACTION_EXTEND as u8;
+            ret.params.extend.length_us = length_us;
+        }
+        Action::End => {
+            ret.callback_action = raw::NRF_RADIO_SIGNAL_CALLBACK_ACTION_NRF_RADIO_SIGNAL_CALLBACK_ACTION_END as u8;
+            ENDED.fetch_add(1, Ordering::Release);
+            crate::events::pend_swi();
+        }
+        Action::RequestAndEnd(request) => {
+            NEXT_REQUEST = request.to_raw();
+            ret.callback_action =
+                raw::NRF_RADIO_SIGNAL_CALLBACK_ACTION_NRF_RADIO_SIGNAL_CALLBACK_ACTION_REQUEST_AND_END as u8;
+            ret.params.request.p_next = ptr::addr_of_mut!(NEXT_REQUEST);
+            ENDED.fetch_add(1, Ordering::Release);
+            crate::events::pend_swi();
+        }
+    }
+
+    ret
+}
+
+pub(crate) fn on_soc_evt(evt: SocEvent) {
+    let flag = match evt {
+        SocEvent::RadioBlocked => FLAG_BLOCKED,
+        SocEvent::RadioCanceled => FLAG_CANCELED,
+        SocEvent::RadioSignalCallbackInvalidReturn => FLAG_INVALID_RETURN,
+        SocEvent::RadioSessionIdle => FLAG_IDLE,
+        SocEvent::RadioSessionClosed => {
+            SESSION_OPEN.store(false, Ordering::Release);
+            FLAG_CLOSED
+        }
+        _ => return,
+    };
+    FLAGS.fetch_or(flag, Ordering::AcqRel);
+    WAKER.wake();
+}
+
+/// Whether a session is open, or still closing.
+pub(crate) fn is_session_open() -> bool {
+    SESSION_OPEN.load(Ordering::Acquire)
+}
+
+pub(crate) fn on_swi() {
+    WAKER.wake();
+    crate::esb::on_swi();
+}
+
+/// An open radio timeslot session.
+///
+/// Only one session can be open at a time. Dropping the session closes it, but another one can only
+/// be opened once the softdevice has finished closing it, see [`close`](Self::close).
+pub struct TimeslotSession {
+    started: u32,
+    ended: u32,
+    // Prevent Send, Sync
+    _private: PhantomData<*mut ()>,
+}
+
+impl TimeslotSession {
+    /// Open a session, using `handler` to handle the signals during timeslots.
+    ///
+    /// No timeslot is scheduled until one is requested with [`request`](Self::request).
+    pub fn open<H: SignalHandler + Send>(_sd: &Softdevice, handler: &'static mut H) -> Result<Self, OpenError> {
+        if SESSION_OPEN
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(OpenError::Busy);
+        }
+
+        let handler: &'static mut (dyn SignalHandler + Send) = handler;
+        unsafe { HANDLER = Some(NonNull::from(handler)) };
+        FLAGS.store(0, Ordering::Release);
+
+        let ret = unsafe { raw::sd_radio_session_open(Some(signal_callback)) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_radio_session_open err {:?}", err);
+            SESSION_OPEN.store(false, Ordering::Release);
+            return Err(err.into());
+        }
+
+        Ok(Self {
+            started: STARTED.load(Ordering::Acquire),
+            ended: ENDED.load(Ordering::Acquire),
+            _private: PhantomData,
+        })
+    }
+
+    /// Request a timeslot.
+    ///
+    /// Only one timeslot can be pending at a time: further ones are requested by the signal handler
+    /// with [`Action::RequestAndEnd`], or with this function once the session is idle again.
+    pub fn request(&mut self, request: Request) -> Result<(), RawError> {
+        FLAGS.fetch_and(!(FLAG_BLOCKED | FLAG_CANCELED | FLAG_IDLE), Ordering::AcqRel);
+
+        let request = request.to_raw();
+        let ret = unsafe { raw::sd_radio_request(&request) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_radio_request err {:?}", err);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Wait for the start of the next timeslot.
+    pub async fn wait_slot_start(&mut self) -> Result<(), TimeslotError> {
+        let mut seen = self.started;
+        let res = wait_count(&STARTED, &mut seen).await;
+        self.started = seen;
+        res
+    }
+
+    /// Wait for the end of the current or next timeslot.
+    pub async fn wait_slot_end(&mut self) -> Result<(), TimeslotError> {
+        let mut seen = self.ended;
+        let res = wait_count(&ENDED, &mut seen).await;
+        self.ended = seen;
+        res
+    }
+
+    /// Wait until the session is idle, i.e. no timeslot is scheduled anymore.
+    pub async fn wait_idle(&mut self) {
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if FLAGS.fetch_and(!FLAG_IDLE, Ordering::AcqRel) & FLAG_IDLE != 0 {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Close the session, ending the current timeslot and canceling the scheduled ones, and wait
+    /// until it is closed.
+    pub async fn close(self) {
+        Self::close_raw();
+        core::mem::forget(self);
+
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if SESSION_OPEN.load(Ordering::Acquire) {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+
+    fn close_raw() {
+        let ret = unsafe { raw::sd_radio_session_close() };
+        if let Err(_err) = RawError::convert(ret) {
+            warn!("sd_radio_session_close err {:?}", _err);
+        }
+    }
+}
+
+impl Drop for TimeslotSession {
+    fn drop(&mut self) {
+        Self::close_raw();
+    }
+}
+
+async fn wait_count(count: &AtomicU32, seen: &mut u32) -> Result<(), TimeslotError> {
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+
+        if count.load(Ordering::Acquire) != *seen {
+            *seen = seen.wrapping_add(1);
+            return Poll::Ready(Ok(()));
+        }
+
+        const ERRORS: [(u32, TimeslotError); 3] = [
+            (FLAG_BLOCKED, TimeslotError::Blocked),
+            (FLAG_CANCELED, TimeslotError::Canceled),
+            (FLAG_INVALID_RETURN, TimeslotError::InvalidReturn),
+        ];
+        for (flag, err) in ERRORS {
+            if FLAGS.fetch_and(!flag, Ordering::AcqRel) & flag != 0 {
+                return Poll::Ready(Err(err));
+            }
+        }
+
+        if FLAGS.load(Ordering::Acquire) & FLAG_CLOSED != 0 {
+            Poll::Ready(Err(TimeslotError::Closed))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
@@ -0,0 +1,147 @@
+//! Radio notifications, signalling the start and end of the softdevice's radio activity.
+//!
+//! Once configured with [`configure`], the softdevice raises the SWI1 interrupt a given distance
+//! before each radio event starts and/or right after it ends. This can be used to pause noisy
+//! peripherals, such as ADC sampling or switching loads, while the radio is active.
+//!
+//! The application must define the `SWI1_EGU1` interrupt handler and call [`on_interrupt`] from it,
+//! so SWI1 stays available to applications not using notifications. As for any other interrupt, its
+//! priority must be set to a level not reserved by the softdevice before configuring notifications.
+//!
+//! ```ignore
+//! #[interrupt]
+//! fn SWI1_EGU1() {
+//!     nrf_softdevice::radio_notification::on_interrupt();
+//! }
+//! ```
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use core::task::Poll;
+
+use embassy_sync::waitqueue::AtomicWaker;
+use futures::future::poll_fn;
+
+use crate::{pac, raw, RawError, Softdevice};
+
+/// Which radio notifications to raise.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum NotificationType {
+    /// No notifications.
+    None = raw::NRF_RADIO_NOTIFICATION_TYPES_NRF_RADIO_NOTIFICATION_TYPE_NONE as u8,
+    /// Notify before the radio becomes active.
+    Active = raw::NRF_RADIO_NOTIFICATION_TYPES_NRF_RADIO_NOTIFICATION_TYPE_INT_ON_ACTIVE as u8,
+    /// Notify after the radio becomes inactive.
+    Inactive = raw::NRF_RADIO_NOTIFICATION_TYPES_NRF_RADIO_NOTIFICATION_TYPE_INT_ON_INACTIVE as u8,
+    /// Notify before the radio becomes active and after it becomes inactive.
+    ///
+    /// The softdevice raises the same interrupt for both, so they're told apart by alternating,
+    /// starting with active. If the interrupt is held off long enough for two notifications to be
+    /// merged, for example by a higher priority interrupt or a long critical section, active and
+    /// inactive get swapped. Configuring notifications again resynchronises them.
+    Both = raw::NRF_RADIO_NOTIFICATION_TYPES_NRF_RADIO_NOTIFICATION_TYPE_INT_ON_BOTH as u8,
+}
+
+/// Distance between the active notification and the start of the radio activity.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum NotificationDistance {
+    None = raw::NRF_RADIO_NOTIFICATION_DISTANCES_NRF_RADIO_NOTIFICATION_DISTANCE_NONE as u8,
+    Us800 = raw::NRF_RADIO_NOTIFICATION_DISTANCES_NRF_RADIO_NOTIFICATION_DISTANCE_800US as u8,
+    Us1740 = raw::NRF_RADIO_NOTIFICATION_DISTANCES_NRF_RADIO_NOTIFICATION_DISTANCE_1740US as u8,
+    Us2680 = raw::NRF_RADIO_NOTIFICATION_DISTANCES_NRF_RADIO_NOTIFICATION_DISTANCE_2680US as u8,
+    Us3620 = raw::NRF_RADIO_NOTIFICATION_DISTANCES_NRF_RADIO_NOTIFICATION_DISTANCE_3620US as u8,
+    Us4560 = raw::NRF_RADIO_NOTIFICATION_DISTANCES_NRF_RADIO_NOTIFICATION_DISTANCE_4560US as u8,
+    Us5500 = raw::NRF_RADIO_NOTIFICATION_DISTANCES_NRF_RADIO_NOTIFICATION_DISTANCE_5500US as u8,
+}
+
+static TYPE: AtomicU8 = AtomicU8::new(NotificationType::None as u8);
+// With `NotificationType::Both`, notifications alternate between active and inactive, see its docs.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static ACTIVE_COUNT: AtomicU32 = AtomicU32::new(0);
+static INACTIVE_COUNT: AtomicU32 = AtomicU32::new(0);
+static ACTIVE_WAKER: AtomicWaker = AtomicWaker::new();
+static INACTIVE_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Configure radio notifications.
+///
+/// This must be done while there's no radio activity, preferably right after enabling the softdevice,
+/// otherwise [`RawError::InvalidState`] is returned. `distance` is ignored for [`NotificationType::None`]
+/// and [`NotificationType::Inactive`].
+///
+/// Notifications may shorten connection events, as the softdevice needs time to signal them.
+pub fn configure(
+    _sd: &Softdevice,
+    notification_type: NotificationType,
+    distance: NotificationDistance,
+) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_radio_notification_cfg_set(notification_type as u8, distance as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_radio_notification_cfg_set err {:?}", err);
+        return Err(err);
+    }
+
+    TYPE.store(notification_type as u8, Ordering::Relaxed);
+    ACTIVE.store(false, Ordering::Relaxed);
+
+    if notification_type == NotificationType::None {
+        pac::NVIC::mask(pac::interrupt::SWI1_EGU1);
+    } else {
+        unsafe { pac::NVIC::unmask(pac::interrupt::SWI1_EGU1) };
+    }
+    Ok(())
+}
+
+/// Wait until the radio is about to become active.
+///
+/// Never completes unless notifications were configured with [`NotificationType::Active`] or [`NotificationType::Both`].
+pub async fn wait_radio_active() {
+    wait(&ACTIVE_COUNT, &ACTIVE_WAKER).await
+}
+
+/// Wait until the radio becomes inactive.
+///
+/// Never completes unless notifications were configured with [`NotificationType::Inactive`] or [`NotificationType::Both`].
+pub async fn wait_radio_inactive() {
+    wait(&INACTIVE_COUNT, &INACTIVE_WAKER).await
+}
+
+async fn wait(count: &AtomicU32, waker: &AtomicWaker) {
+    let start = count.load(Ordering::Acquire);
+    poll_fn(|cx| {
+        waker.register(cx.waker());
+        if count.load(Ordering::Acquire) != start {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// The softdevice forgets the configuration when disabled.
+pub(crate) fn on_softdevice_disabled() {
+    TYPE.store(NotificationType::None as u8, Ordering::Relaxed);
+    ACTIVE.store(false, Ordering::Relaxed);
+    pac::NVIC::mask(pac::interrupt::SWI1_EGU1);
+}
+
+/// Handle a radio notification. Must be called from the application's `SWI1_EGU1` interrupt handler.
+pub fn on_interrupt() {
+    let active = match TYPE.load(Ordering::Relaxed) {
+        t if t == NotificationType::Active as u8 => true,
+        t if t == NotificationType::Inactive as u8 => false,
+        t if t == NotificationType::Both as u8 => !ACTIVE.fetch_xor(true, Ordering::Relaxed),
+        _ => return,
+    };
+
+    if active {
+        ACTIVE_COUNT.fetch_add(1, Ordering::Release);
+        ACTIVE_WAKER.wake();
+    } else {
+        INACTIVE_COUNT.fetch_add(1, Ordering::Release);
+        INACTIVE_WAKER.wake();
+    }
+}
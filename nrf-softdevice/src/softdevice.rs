@@ -3,6 +3,7 @@ use core::mem::MaybeUninit;
 use core::ptr;
 use core::sync::atomic::{AtomicBool, Ordering};
 
+use crate::util::OnDrop;
 use crate::{pac, raw, RawError, SocEvent};
 
 unsafe extern "C" fn fault_handler(id: u32, pc: u32, info: u32) {
@@ -31,7 +32,7 @@ unsafe extern "C" fn fault_handler(id: u32, pc: u32, info: u32) {
 /// The `Softdevice` instance can be obtaind by enabling it with [`Softdevice::enable`]. Once
 /// enabled, it can be used to establish Bluetooth connections with [`ble::central`] and [`ble::peripheral`].
 ///
-/// Disabling the softdevice is not supported due to the complexity of a safe implementation. Consider resetting the CPU instead.
+/// It can be disabled again with [`Softdevice::disable`], for example to use the radio directly.
 pub struct Softdevice {
     // Prevent Send, Sync
     _private: PhantomData<*mut ()>,
@@ -42,6 +43,30 @@ pub struct Softdevice {
     pub(crate) l2cap_rx_mps: u16,
 }
 
+/// Error returned by [`Softdevice::disable`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisableError {
+    /// A connection is established, or a [`Connection`](crate::ble::Connection) instance is still alive.
+    ConnectionsActive,
+    /// The [`Flash`](crate::Flash) instance is still alive.
+    FlashInUse,
+    /// The [`L2cap`](crate::ble::l2cap::L2cap) instance is still alive.
+    #[cfg(feature = "ble-l2cap")]
+    L2capInUse,
+    /// An [`HfclkGuard`](crate::clock::HfclkGuard) is still alive.
+    HfclkInUse,
+    /// The [`UsbSupply`](crate::UsbSupply) instance is still alive.
+    #[cfg(any(feature = "s113", feature = "s122", feature = "s140"))]
+    UsbSupplyInUse,
+    /// A PPI [`Channel`](crate::ppi::Channel) or [`Group`](crate::ppi::Group) is still alive.
+    PpiInUse,
+    /// A [`TimeslotSession`](crate::radio::TimeslotSession) is still open, or the softdevice hasn't
+    /// finished closing it.
+    TimeslotSessionOpen,
+    Raw(RawError),
+}
+
 /// Softdevice configuration.
 ///
 /// Fields set to None will use a default configuration.
@@ -73,13 +98,38 @@ fn get_app_ram_base() -> u32 {
     unsafe { ptr::addr_of!(__sdata) as u32 }
 }
 
-fn cfg_set(id: u32, cfg: &raw::ble_cfg_t) {
+/// Error returned by [`Softdevice::try_enable`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EnableError {
+    /// The softdevice is already enabled.
+    AlreadyEnabled,
+    /// `sd_softdevice_enable` failed, usually because of an invalid clock configuration.
+    Softdevice(RawError),
+    /// `sd_ble_cfg_set` rejected the configuration with the given ID (one of the `raw::BLE_*_CFG_*` constants).
+    Config { id: u32, err: RawError },
+    /// Too little RAM is reserved for the softdevice. The app's RAM start address must be at least `required_ram_base`.
+    ///
+    /// `id` is the first configuration `sd_ble_cfg_set` reported as not fitting, if any.
+    NoMem { required_ram_base: u32, id: Option<u32> },
+    /// The configuration needs more RAM than the softdevice supports, whatever the RAM start address.
+    ConfigTooLarge,
+    /// `sd_ble_enable` failed.
+    Ble(RawError),
+}
+
+/// Set a BLE config. If there's not enough RAM for it, `no_mem_id` is set to its ID if it's the first
+/// such config, and the error is deferred to `sd_ble_enable` which reports how much RAM is needed.
+fn cfg_set(id: u32, cfg: &raw::ble_cfg_t, no_mem_id: &mut Option<u32>) -> Result<(), EnableError> {
     let app_ram_base = get_app_ram_base();
     let ret = unsafe { raw::sd_ble_cfg_set(id, cfg, app_ram_base) };
     match RawError::convert(ret) {
-        Ok(()) => {}
-        Err(RawError::NoMem) => {}
-        Err(err) => panic!("sd_ble_cfg_set {:?} err {:?}", id, err),
+        Ok(()) => Ok(()),
+        Err(RawError::NoMem) => {
+            no_mem_id.get_or_insert(id);
+            Ok(())
+        }
+        Err(err) => Err(EnableError::Config { id, err }),
     }
 }
 
@@ -93,22 +143,47 @@ impl Softdevice {
     /// - Panics if the requested configuration requires more memory than reserved for the softdevice. In that case, you can give more memory to the softdevice by editing the RAM start address in `memory.x`. The required start address is logged prior to panic.
     /// - Panics if the requested configuration has too high memory requirements for the softdevice. The softdevice supports a maximum dynamic memory size of 64kb.
     /// - Panics if called multiple times. Must be called at most once.
+    ///
+    /// See [`Softdevice::try_enable`] for a version returning an error instead.
     pub fn enable(config: &Config) -> &'static mut Softdevice {
+        match Self::try_enable(config) {
+            Ok(sd) => sd,
+            Err(EnableError::AlreadyEnabled) => panic!("nrf_softdevice::enable() called multiple times."),
+            Err(EnableError::Softdevice(err)) => panic!("sd_softdevice_enable err {:?}", err),
+            Err(EnableError::Config { id, err }) => panic!("sd_ble_cfg_set {:?} err {:?}", id, err),
+            Err(EnableError::NoMem { required_ram_base, id }) => panic!(
+                "too little RAM for softdevice (config {:?}). Change your app's RAM start address to {:x}",
+                id, required_ram_base
+            ),
+            Err(EnableError::ConfigTooLarge) => panic!("selected configuration has too high RAM requirements."),
+            Err(EnableError::Ble(err)) => panic!("sd_ble_enable err {:?}", err),
+        }
+    }
+
+    /// Enable the softdevice, returning an error instead of panicking if it fails.
+    ///
+    /// On error, the softdevice is left disabled, so this can be called again, for example
+    /// with a configuration needing less RAM.
+    pub fn try_enable(config: &Config) -> Result<&'static mut Softdevice, EnableError> {
         if ENABLED
             .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
             .is_err()
         {
-            panic!("nrf_softdevice::enable() called multiple times.")
+            return Err(EnableError::AlreadyEnabled);
         }
+        let enabled = OnDrop::new(|| ENABLED.store(false, Ordering::Release));
 
         let p_clock_lf_cfg = config.clock.as_ref().map(|x| x as _).unwrap_or(ptr::null());
         let ret = unsafe { raw::sd_softdevice_enable(p_clock_lf_cfg, Some(fault_handler)) };
-        match RawError::convert(ret) {
-            Ok(()) => {}
-            Err(err) => panic!("sd_softdevice_enable err {:?}", err),
-        }
+        RawError::convert(ret).map_err(EnableError::Softdevice)?;
+
+        // Disable the softdevice again if the BLE configuration fails.
+        let sd_enabled = OnDrop::new(|| unsafe {
+            raw::sd_softdevice_disable();
+        });
 
         let app_ram_base = get_app_ram_base();
+        let mut no_mem_id = None;
 
         // Set at least one GAP config so conn_cfg_tag 1 (APP_CONN_CFG_TAG) is usable.
         // If you set none, it seems the softdevice won't let you use it, requiring a conn_cfg_tag of 0 (raw::BLE_CONN_CFG_TAG_DEFAULT) instead.
@@ -124,7 +199,8 @@ impl Softdevice {
                     params: raw::ble_conn_cfg_t__bindgen_ty_1 { gap_conn_cfg: val },
                 },
             },
-        );
+            &mut no_mem_id,
+        )?;
 
         if let Some(val) = config.conn_gatt {
             cfg_set(
@@ -135,7 +211,8 @@ impl Softdevice {
                         params: raw::ble_conn_cfg_t__bindgen_ty_1 { gatt_conn_cfg: val },
                     },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         if let Some(val) = config.conn_gattc {
@@ -147,7 +224,8 @@ impl Softdevice {
                         params: raw::ble_conn_cfg_t__bindgen_ty_1 { gattc_conn_cfg: val },
                     },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         if let Some(val) = config.conn_gatts {
@@ -159,7 +237,8 @@ impl Softdevice {
                         params: raw::ble_conn_cfg_t__bindgen_ty_1 { gatts_conn_cfg: val },
                     },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         #[cfg(feature = "ble-l2cap")]
@@ -172,7 +251,8 @@ impl Softdevice {
                         params: raw::ble_conn_cfg_t__bindgen_ty_1 { l2cap_conn_cfg: val },
                     },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         if let Some(val) = config.common_vs_uuid {
@@ -181,7 +261,8 @@ impl Softdevice {
                 &raw::ble_cfg_t {
                     common_cfg: raw::ble_common_cfg_t { vs_uuid_cfg: val },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         if let Some(val) = config.gap_role_count {
@@ -190,7 +271,8 @@ impl Softdevice {
                 &raw::ble_cfg_t {
                     gap_cfg: raw::ble_gap_cfg_t { role_count_cfg: val },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         if let Some(val) = config.gap_device_name {
@@ -199,7 +281,8 @@ impl Softdevice {
                 &raw::ble_cfg_t {
                     gap_cfg: raw::ble_gap_cfg_t { device_name_cfg: val },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         if let Some(val) = config.gap_ppcp_incl {
@@ -208,7 +291,8 @@ impl Softdevice {
                 &raw::ble_cfg_t {
                     gap_cfg: raw::ble_gap_cfg_t { ppcp_include_cfg: val },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         if let Some(val) = config.gap_car_incl {
@@ -217,7 +301,8 @@ impl Softdevice {
                 &raw::ble_cfg_t {
                     gap_cfg: raw::ble_gap_cfg_t { car_include_cfg: val },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
         if let Some(val) = config.gatts_service_changed {
             cfg_set(
@@ -225,7 +310,8 @@ impl Softdevice {
                 &raw::ble_cfg_t {
                     gatts_cfg: raw::ble_gatts_cfg_t { service_changed: val },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
         if let Some(val) = config.gatts_attr_tab_size {
             cfg_set(
@@ -233,7 +319,8 @@ impl Softdevice {
                 &raw::ble_cfg_t {
                     gatts_cfg: raw::ble_gatts_cfg_t { attr_tab_size: val },
                 },
-            );
+                &mut no_mem_id,
+            )?;
         }
 
         let mut wanted_app_ram_base = app_ram_base;
@@ -243,21 +330,31 @@ impl Softdevice {
             Ok(()) => {}
             Err(RawError::NoMem) => {
                 if wanted_app_ram_base <= app_ram_base {
-                    panic!("selected configuration has too high RAM requirements.")
+                    return Err(EnableError::ConfigTooLarge);
                 } else {
-                    panic!(
-                        "too little RAM for softdevice. Change your app's RAM start address to {:x}",
-                        wanted_app_ram_base
-                    );
+                    return Err(EnableError::NoMem {
+                        required_ram_base: wanted_app_ram_base,
+                        id: no_mem_id,
+                    });
                 }
             }
-            Err(err) => panic!("sd_ble_enable err {:?}", err),
+            Err(err) => return Err(EnableError::Ble(err)),
+        }
+        if let Some(id) = no_mem_id {
+            // Not expected, as sd_ble_enable should fail too, but the config wasn't applied.
+            return Err(EnableError::Config {
+                id,
+                err: RawError::NoMem,
+            });
         }
 
         if wanted_app_ram_base < app_ram_base {
             warn!("You're giving more RAM to the softdevice than needed. You can change your app's RAM start address to {:x}", wanted_app_ram_base);
         }
 
+        sd_enabled.defuse();
+        enabled.defuse();
+
         unsafe {
             #[cfg(any(feature = "nrf52805", feature = "nrf52810", feature = "nrf52811"))]
             pac::NVIC::unmask(pac::interrupt::SWI2);
@@ -290,10 +387,68 @@ impl Softdevice {
         unsafe {
             let p = SOFTDEVICE.as_mut_ptr();
             p.write(sd);
-            &mut *p
+            Ok(&mut *p)
         }
     }
 
+    /// Disable the softdevice.
+    ///
+    /// This takes the `&'static mut Softdevice` returned by [`Softdevice::enable`], so it can only be
+    /// used if [`Softdevice::run`] is not running in a separate task. Instead, run it in the same task
+    /// with e.g. `select`, and drop its future before disabling.
+    ///
+    /// All connections and timeslot sessions must be closed, and the [`Flash`](crate::Flash),
+    /// [`L2cap`](crate::ble::l2cap::L2cap) and [`UsbSupply`](crate::UsbSupply) instances, the
+    /// [`HfclkGuard`](crate::clock::HfclkGuard)s and the PPI [`Channel`](crate::ppi::Channel)s and
+    /// [`Group`](crate::ppi::Group)s dropped, otherwise this returns an error along with the softdevice.
+    ///
+    /// Once disabled, the softdevice releases the radio, timers and interrupts it reserved, and can
+    /// be enabled again with [`Softdevice::enable`]. GATT servers, advertisements, radio notifications
+    /// etc. must then be set up again.
+    pub fn disable(&'static mut self) -> Result<(), (&'static mut Softdevice, DisableError)> {
+        if crate::ble::any_connection_active() {
+            return Err((self, DisableError::ConnectionsActive));
+        }
+        if crate::flash::is_taken() {
+            return Err((self, DisableError::FlashInUse));
+        }
+        #[cfg(feature = "ble-l2cap")]
+        if crate::ble::l2cap::is_init() {
+            return Err((self, DisableError::L2capInUse));
+        }
+        if crate::clock::is_requested() {
+            return Err((self, DisableError::HfclkInUse));
+        }
+        #[cfg(any(feature = "s113", feature = "s122", feature = "s140"))]
+        if crate::usb_supply::is_taken() {
+            return Err((self, DisableError::UsbSupplyInUse));
+        }
+        if crate::ppi::any_allocated() {
+            return Err((self, DisableError::PpiInUse));
+        }
+        if crate::radio::is_session_open() {
+            return Err((self, DisableError::TimeslotSessionOpen));
+        }
+
+        let ret = unsafe { raw::sd_softdevice_disable() };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_softdevice_disable err {:?}", err);
+            return Err((self, DisableError::Raw(err)));
+        }
+
+        #[cfg(any(feature = "nrf52805", feature = "nrf52810", feature = "nrf52811"))]
+        pac::NVIC::mask(pac::interrupt::SWI2);
+        #[cfg(not(any(feature = "nrf52805", feature = "nrf52810", feature = "nrf52811")))]
+        pac::NVIC::mask(pac::interrupt::SWI2_EGU2);
+
+        #[cfg(feature = "ble-peripheral")]
+        crate::ble::peripheral::on_softdevice_disabled();
+        crate::radio_notification::on_softdevice_disabled();
+
+        ENABLED.store(false, Ordering::Release);
+        Ok(())
+    }
+
     /// Return an instance to the softdevice without checking whether
     /// it is enabled or not. This is only safe if the softdevice is enabled
     /// (a call to [`enable`] has returned without error) and no `&mut` references
@@ -0,0 +1,224 @@
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::task::{Context, Poll};
+
+#[cfg(feature = "embassy-nrf")]
+use embassy_nrf::usb::vbus_detect::{SoftwareVbusDetect, VbusDetect};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use futures::future::poll_fn;
+
+use crate::{raw, RawError, SocEvent, Softdevice};
+
+const USBREGSTATUS_VBUSDETECT: u32 = 1 << 0;
+const USBREGSTATUS_OUTPUTRDY: u32 = 1 << 1;
+
+/// Singleton instance of the USB supply detection, for chips with a USB peripheral.
+///
+/// While the softdevice is enabled, the USB power events are reported through the softdevice
+/// instead of the POWER peripheral. This tracks them so `embassy-nrf`'s USB driver can be used
+/// alongside the softdevice: with the `embassy-nrf` feature, `&UsbSupply` implements its `VbusDetect`
+/// trait.
+///
+/// ```ignore
+/// let driver = Driver::new(p.USBD, Irqs, &supply);
+/// ```
+///
+/// Without the feature, [`run`](Self::run) can forward the events to a `SoftwareVbusDetect` instead.
+///
+/// The events are still passed to the [`Softdevice::run_with_callback`] callback.
+pub struct UsbSupply {
+    // Prevent Send, Sync
+    _private: PhantomData<*mut ()>,
+}
+
+static USB_SUPPLY_TAKEN: AtomicBool = AtomicBool::new(false);
+static DETECTED: AtomicBool = AtomicBool::new(false);
+static POWER_READY: AtomicBool = AtomicBool::new(false);
+// Counts removals, so a removal quickly followed by a detection isn't missed.
+static REMOVALS: AtomicU32 = AtomicU32::new(0);
+static WAKERS: Mutex<CriticalSectionRawMutex, RefCell<MultiWakerRegistration<4>>> =
+    Mutex::new(RefCell::new(MultiWakerRegistration::new()));
+// embassy-nrf's USB driver only checks VBUS again when its bus waker is woken, which only its own
+// VBUS detectors can do. This one is kept in sync with the events, just to wake the driver up.
+#[cfg(feature = "embassy-nrf")]
+static DRIVER_VBUS: Mutex<CriticalSectionRawMutex, RefCell<Option<SoftwareVbusDetect>>> =
+    Mutex::new(RefCell::new(None));
+
+impl UsbSupply {
+    /// Takes the UsbSupply instance from the softdevice, enabling the USB power events.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called while another UsbSupply instance is alive.
+    pub fn take(_sd: &Softdevice) -> Result<UsbSupply, RawError> {
+        if USB_SUPPLY_TAKEN
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            panic!("nrf_softdevice::UsbSupply::take() called multiple times.")
+        }
+        let supply = UsbSupply { _private: PhantomData };
+
+        set_events_enabled(true)?;
+
+        // Events only report changes, get the current state.
+        let mut status: u32 = 0;
+        let ret = unsafe { raw::sd_power_usbregstatus_get(&mut status) };
+        if let Err(err) = RawError::convert(ret) {
+            warn!("sd_power_usbregstatus_get err {:?}", err);
+            return Err(err);
+        }
+        let detected = status & USBREGSTATUS_VBUSDETECT != 0;
+        let power_ready = status & USBREGSTATUS_OUTPUTRDY != 0;
+        DETECTED.store(detected, Ordering::Release);
+        POWER_READY.store(power_ready, Ordering::Release);
+        #[cfg(feature = "embassy-nrf")]
+        DRIVER_VBUS.lock(|v| *v.borrow_mut() = Some(SoftwareVbusDetect::new(detected, power_ready)));
+
+        Ok(supply)
+    }
+
+    /// Whether VBUS is detected.
+    pub fn is_usb_detected(&self) -> bool {
+        DETECTED.load(Ordering::Acquire)
+    }
+
+    /// Whether the USB regulator output is ready, so the USB peripheral can be enabled.
+    pub fn is_power_ready(&self) -> bool {
+        POWER_READY.load(Ordering::Acquire)
+    }
+
+    /// Wait until VBUS is detected.
+    pub async fn wait_usb_detected(&self) {
+        poll_fn(|cx| {
+            register(cx);
+            if self.is_usb_detected() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Wait until VBUS is removed.
+    pub async fn wait_usb_removed(&self) {
+        let removals = REMOVALS.load(Ordering::Acquire);
+        poll_fn(|cx| {
+            register(cx);
+            if !self.is_usb_detected() || REMOVALS.load(Ordering::Acquire) != removals {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Wait until the USB regulator output is ready.
+    ///
+    /// Returns `Err` if VBUS is removed while waiting.
+    pub async fn wait_power_ready(&self) -> Result<(), ()> {
+        let removals = REMOVALS.load(Ordering::Acquire);
+        poll_fn(|cx| {
+            register(cx);
+            if !self.is_usb_detected() || REMOVALS.load(Ordering::Acquire) != removals {
+                Poll::Ready(Err(()))
+            } else if self.is_power_ready() {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Report every change of the USB supply state, starting with the current one: `detected` is
+    /// called when VBUS is detected or removed, and `ready` when the USB regulator output is ready.
+    ///
+    /// These match the `detected` and `ready` methods of `embassy-nrf`'s `SoftwareVbusDetect`.
+    pub async fn run(&self, mut detected: impl FnMut(bool), mut ready: impl FnMut()) -> ! {
+        loop {
+            self.wait_usb_detected().await;
+            detected(true);
+            if self.wait_power_ready().await.is_ok() {
+                ready();
+                self.wait_usb_removed().await;
+            }
+            detected(false);
+        }
+    }
+}
+
+impl Drop for UsbSupply {
+    fn drop(&mut self) {
+        let _ = set_events_enabled(false);
+        USB_SUPPLY_TAKEN.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(feature = "embassy-nrf")]
+impl VbusDetect for &UsbSupply {
+    fn is_usb_detected(&self) -> bool {
+        UsbSupply::is_usb_detected(self)
+    }
+
+    async fn wait_power_ready(&mut self) -> Result<(), ()> {
+        UsbSupply::wait_power_ready(self).await
+    }
+}
+
+fn register(cx: &mut Context<'_>) {
+    WAKERS.lock(|w| w.borrow_mut().register(cx.waker()));
+}
+
+pub(crate) fn is_taken() -> bool {
+    USB_SUPPLY_TAKEN.load(Ordering::Acquire)
+}
+
+fn set_events_enabled(enable: bool) -> Result<(), RawError> {
+    let ret = unsafe { raw::sd_power_usbdetected_enable(enable as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_usbdetected_enable err {:?}", err);
+        return Err(err);
+    }
+    let ret = unsafe { raw::sd_power_usbpwrrdy_enable(enable as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_usbpwrrdy_enable err {:?}", err);
+        return Err(err);
+    }
+    let ret = unsafe { raw::sd_power_usbremoved_enable(enable as u8) };
+    if let Err(err) = RawError::convert(ret) {
+        warn!("sd_power_usbremoved_enable err {:?}", err);
+        return Err(err);
+    }
+    Ok(())
+}
+
+pub(crate) fn on_soc_evt(evt: SocEvent) {
+    match evt {
+        SocEvent::PowerUsbDetected => DETECTED.store(true, Ordering::Release),
+        SocEvent::PowerUsbPowerReady => POWER_READY.store(true, Ordering::Release),
+        SocEvent::PowerUsbRemoved => {
+            DETECTED.store(false, Ordering::Release);
+            POWER_READY.store(false, Ordering::Release);
+            REMOVALS.fetch_add(1, Ordering::AcqRel);
+        }
+        _ => return,
+    }
+    WAKERS.lock(|w| w.borrow_mut().wake());
+
+    #[cfg(feature = "embassy-nrf")]
+    DRIVER_VBUS.lock(|v| {
+        if let Some(vbus) = &*v.borrow() {
+            match evt {
+                SocEvent::PowerUsbDetected => vbus.detected(true),
+                SocEvent::PowerUsbPowerReady => vbus.ready(),
+                _ => vbus.detected(false),
+            }
+        }
+    });
+}